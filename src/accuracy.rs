@@ -0,0 +1,434 @@
+use crate::renderer::{AccumulationMode, ConservativeMode, Renderer, VoxelizationMode};
+use crate::scene::camera::Camera;
+use crate::scene::description::load_scene;
+use crate::textures::{Volume, VolumeStorage};
+use failure::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+pub struct AccuracyConfig {
+	pub scenes: Vec<String>,
+	pub resolutions: Vec<usize>,
+	pub modes: Vec<VoxelizationMode>,
+	#[serde(default = "default_conservative")]
+	pub conservative: Vec<ConservativeMode>,
+	pub cutoffs: Vec<f32>,
+	#[serde(default = "default_reference_scale")]
+	pub reference_scale: usize,
+	pub output: String,
+}
+
+impl AccuracyConfig {
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<AccuracyConfig, Error> {
+		let path = path.as_ref();
+		let source = fs::read_to_string(path)
+			.map_err(|e| format_err!("Couldn't read accuracy sweep '{}': {}", path.display(), e))?;
+
+		ron::de::from_str(&source)
+			.map_err(|e| format_err!("Couldn't parse accuracy sweep '{}': {}", path.display(), e))
+	}
+}
+
+// Occupancy and attributes of a voxelization, read back from the GPU
+#[derive(Clone)]
+pub struct VoxelGrid {
+	resolution: usize,
+	albedo: Vec<[u8; 4]>,
+	normal: Vec<[u8; 4]>,
+}
+
+impl VoxelGrid {
+	pub fn from_volume(volume: &Volume) -> VoxelGrid {
+		VoxelGrid {
+			resolution: volume.resolution()[0] as usize,
+			albedo: volume.read_albedo(),
+			normal: volume.read_normal(),
+		}
+	}
+
+	fn index(&self, x: usize, y: usize, z: usize) -> usize {
+		x + self.resolution * (y + self.resolution * z)
+	}
+
+	// The voxelizers store how many samples were averaged in the alpha channel
+	fn is_occupied(&self, index: usize) -> bool {
+		self.albedo[index][3] > 0
+	}
+
+	// Voxels with 255 samples or more, past which the packed running average stops counting
+	pub fn saturated_count(&self) -> usize {
+		self.albedo.iter().filter(|albedo| albedo[3] == 255).count()
+	}
+
+	// A coarse voxel is occupied if any of its fine voxels is, attributes are averaged
+	pub fn downsample(&self, factor: usize) -> VoxelGrid {
+		if factor <= 1 {
+			return self.clone();
+		}
+
+		let resolution = self.resolution / factor;
+		let cells = resolution * resolution * resolution;
+		let mut albedo = vec![[0u8; 4]; cells];
+		let mut normal = vec![[0u8; 4]; cells];
+
+		for z in 0..resolution {
+			for y in 0..resolution {
+				for x in 0..resolution {
+					let mut count = 0;
+					let mut albedo_sum = [0u32; 3];
+					let mut normal_sum = [0u32; 3];
+
+					for dz in 0..factor {
+						for dy in 0..factor {
+							for dx in 0..factor {
+								let index = self.index(x * factor + dx, y * factor + dy, z * factor + dz);
+								if !self.is_occupied(index) {
+									continue;
+								}
+
+								count += 1;
+								for c in 0..3 {
+									albedo_sum[c] += u32::from(self.albedo[index][c]);
+									normal_sum[c] += u32::from(self.normal[index][c]);
+								}
+							}
+						}
+					}
+
+					if count > 0 {
+						let target = x + resolution * (y + resolution * z);
+						for c in 0..3 {
+							albedo[target][c] = (albedo_sum[c] / count) as u8;
+							normal[target][c] = (normal_sum[c] / count) as u8;
+						}
+						albedo[target][3] = 255;
+						normal[target][3] = 255;
+					}
+				}
+			}
+		}
+
+		VoxelGrid {
+			resolution,
+			albedo,
+			normal,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct AccuracyReport {
+	pub true_positives: usize,
+	pub false_positives: usize,
+	pub false_negatives: usize,
+	pub iou: f32,
+	// Mean absolute error over the voxels both grids agree are occupied
+	pub albedo_error: [f32; 3],
+	pub normal_error: [f32; 3],
+}
+
+pub fn compare(grid: &VoxelGrid, reference: &VoxelGrid) -> AccuracyReport {
+	assert_eq!(
+		grid.resolution, reference.resolution,
+		"Only grids of the same resolution can be compared"
+	);
+
+	let mut report = AccuracyReport::default();
+	let mut albedo_error = [0.0f64; 3];
+	let mut normal_error = [0.0f64; 3];
+
+	for index in 0..grid.albedo.len() {
+		match (grid.is_occupied(index), reference.is_occupied(index)) {
+			(true, true) => {
+				report.true_positives += 1;
+				for c in 0..3 {
+					let albedo_delta =
+						f64::from(grid.albedo[index][c]) - f64::from(reference.albedo[index][c]);
+					let normal_delta =
+						f64::from(grid.normal[index][c]) - f64::from(reference.normal[index][c]);
+					albedo_error[c] += albedo_delta.abs() / 255.0;
+					// Normals are encoded in [0, 1], the error is measured in [-1, 1]
+					normal_error[c] += normal_delta.abs() / 255.0 * 2.0;
+				}
+			}
+			(true, false) => report.false_positives += 1,
+			(false, true) => report.false_negatives += 1,
+			(false, false) => (),
+		}
+	}
+
+	let union = report.true_positives + report.false_positives + report.false_negatives;
+	report.iou = if union == 0 {
+		1.0
+	} else {
+		report.true_positives as f32 / union as f32
+	};
+
+	if report.true_positives > 0 {
+		for c in 0..3 {
+			report.albedo_error[c] = (albedo_error[c] / report.true_positives as f64) as f32;
+			report.normal_error[c] = (normal_error[c] / report.true_positives as f64) as f32;
+		}
+	}
+
+	report
+}
+
+// The compute voxelizer does an exact triangle/box overlap test, so at a higher resolution it is
+// the closest thing we have to ground truth. It isn't independent though: it shares the triangle
+// setup and attribute sampling of the other voxelizers, so their common bugs go unnoticed, and the
+// compute results mostly measure the downsampling
+pub const REFERENCE_MODE: VoxelizationMode = VoxelizationMode::Compute;
+
+pub fn reference_grid(renderer: &mut Renderer, resolution: usize, scale: usize) -> VoxelGrid {
+	let mode = renderer.voxelization_mode;
+
+	renderer.set_resolution(resolution * scale);
+	renderer.voxelization_mode = REFERENCE_MODE;
+	renderer.voxelize_volume();
+	let reference = VoxelGrid::from_volume(renderer.volume()).downsample(scale);
+
+	renderer.voxelization_mode = mode;
+
+	reference
+}
+
+// Compares the current settings against a reference, leaving the renderer as it was
+pub fn measure_current(renderer: &mut Renderer, scale: usize) -> AccuracyReport {
+	let resolution = renderer.volume().resolution()[0] as usize;
+	let reference = reference_grid(renderer, resolution, scale);
+
+	renderer.set_resolution(resolution);
+	renderer.voxelize_volume();
+
+	compare(&VoxelGrid::from_volume(renderer.volume()), &reference)
+}
+
+#[derive(Clone, Copy)]
+pub struct AccumulationComparison {
+	// The packed averages measured against the resolved sums
+	pub report: AccuracyReport,
+	pub saturated_voxels: usize,
+}
+
+// Voxelizes the current settings with both accumulation modes, leaving the renderer as it was
+pub fn compare_accumulation(renderer: &mut Renderer) -> AccumulationComparison {
+	let accumulation = renderer.accumulation;
+
+	renderer.accumulation = AccumulationMode::Sum;
+	renderer.voxelize_volume();
+	let sums = VoxelGrid::from_volume(renderer.volume());
+
+	renderer.accumulation = AccumulationMode::Packed;
+	renderer.voxelize_volume();
+	let packed = VoxelGrid::from_volume(renderer.volume());
+
+	renderer.accumulation = accumulation;
+
+	AccumulationComparison {
+		report: compare(&packed, &sums),
+		saturated_voxels: sums.saturated_count(),
+	}
+}
+
+#[derive(Serialize)]
+struct Record {
+	scene: String,
+	resolution: usize,
+	mode: &'static str,
+	conservative: &'static str,
+	cutoff: f32,
+	// The voxelizer and scale of the reference grid, e.g. compute_x2
+	reference: String,
+	true_positives: usize,
+	false_positives: usize,
+	false_negatives: usize,
+	iou: f32,
+	albedo_error_r: f32,
+	albedo_error_g: f32,
+	albedo_error_b: f32,
+	normal_error_x: f32,
+	normal_error_y: f32,
+	normal_error_z: f32,
+}
+
+pub fn run_sweep(
+	renderer: &mut Renderer,
+	camera: &mut Camera,
+	config: &AccuracyConfig,
+) -> Result<(), Error> {
+	let mut writer = csv::Writer::from_path(&config.output)?;
+	let reference_name = format!("{}_x{}", REFERENCE_MODE.name(), config.reference_scale);
+	println!(
+		"Measuring against the {} voxelizer at {} times the resolution, not an independent reference",
+		REFERENCE_MODE.name(),
+		config.reference_scale
+	);
+
+	for scene in &config.scenes {
+		let scene_name = load_scene(renderer, camera, scene)?;
+		// The grids are read back from the dense textures
+		renderer.set_storage(VolumeStorage::Dense);
+
+		for &resolution in &config.resolutions {
+			let reference = reference_grid(renderer, resolution, config.reference_scale);
+			renderer.set_resolution(resolution);
+
+			for &mode in &config.modes {
+				if !renderer.capabilities().supports(mode.feature()) {
+					println!("Skipping the unsupported {} voxelizer", mode.name());
+					continue;
+				}
+
+				for &conservative in &config.conservative {
+					if !conservative.is_supported(renderer.capabilities()) {
+						println!(
+							"Skipping unsupported conservative mode '{}'",
+							conservative.name()
+						);
+						continue;
+					}
+
+					for &cutoff in &config.cutoffs {
+						renderer.voxelization_mode = mode;
+						renderer.set_conservative_mode(conservative);
+						renderer.cutoff = cutoff;
+						renderer.voxelize_volume();
+
+						let report = compare(&VoxelGrid::from_volume(renderer.volume()), &reference);
+						println!(
+							"{} {}^3 {} conservative={} cutoff={:.2}: IoU {:.4}, {} false positives, {} false negatives",
+							scene_name,
+							resolution,
+							mode.name(),
+							conservative.name(),
+							cutoff,
+							report.iou,
+							report.false_positives,
+							report.false_negatives
+						);
+
+						writer.serialize(Record {
+							scene: scene_name.clone(),
+							resolution,
+							mode: mode.name(),
+							conservative: conservative.name(),
+							cutoff,
+							reference: reference_name.clone(),
+							true_positives: report.true_positives,
+							false_positives: report.false_positives,
+							false_negatives: report.false_negatives,
+							iou: report.iou,
+							albedo_error_r: report.albedo_error[0],
+							albedo_error_g: report.albedo_error[1],
+							albedo_error_b: report.albedo_error[2],
+							normal_error_x: report.normal_error[0],
+							normal_error_y: report.normal_error[1],
+							normal_error_z: report.normal_error[2],
+						})?;
+					}
+				}
+			}
+		}
+	}
+
+	writer.flush()?;
+	println!("Accuracy results written to '{}'", config.output);
+
+	let capabilities_path = renderer.capabilities().save_next_to(&config.output)?;
+	println!(
+		"GPU capabilities written to '{}'",
+		capabilities_path.display()
+	);
+
+	Ok(())
+}
+
+fn default_conservative() -> Vec<ConservativeMode> {
+	vec![ConservativeMode::Nv, ConservativeMode::GeometryShader]
+}
+
+fn default_reference_scale() -> usize {
+	2
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn grid(resolution: usize, occupied: &[(usize, [u8; 3])]) -> VoxelGrid {
+		let cells = resolution * resolution * resolution;
+		let mut grid = VoxelGrid {
+			resolution,
+			albedo: vec![[0; 4]; cells],
+			normal: vec![[0; 4]; cells],
+		};
+		for &(index, color) in occupied {
+			grid.albedo[index] = [color[0], color[1], color[2], 1];
+			grid.normal[index] = [color[0], color[1], color[2], 1];
+		}
+		grid
+	}
+
+	#[test]
+	fn downsample_averages_the_occupied_voxels() {
+		// Two of the eight fine voxels of the single coarse one
+		let fine = grid(2, &[(0, [10, 20, 30]), (7, [30, 40, 50])]);
+		let coarse = fine.downsample(2);
+
+		assert_eq!(coarse.resolution, 1);
+		assert_eq!(coarse.albedo, vec![[20, 30, 40, 255]]);
+		assert_eq!(coarse.normal, vec![[20, 30, 40, 255]]);
+	}
+
+	#[test]
+	fn downsample_keeps_empty_voxels_empty() {
+		let fine = grid(4, &[(0, [255, 255, 255])]);
+		let coarse = fine.downsample(2);
+
+		assert_eq!(coarse.albedo.len(), 8);
+		assert!(coarse.is_occupied(0));
+		assert!((1..8).all(|index| !coarse.is_occupied(index)));
+	}
+
+	#[test]
+	fn downsample_by_one_is_a_copy() {
+		let fine = grid(2, &[(3, [1, 2, 3])]);
+		let copy = fine.downsample(1);
+
+		assert_eq!(copy.resolution, 2);
+		assert_eq!(copy.albedo, fine.albedo);
+	}
+
+	#[test]
+	fn compare_counts_the_occupancy() {
+		let voxelized = grid(2, &[(0, [0; 3]), (1, [0; 3]), (2, [0; 3])]);
+		let reference = grid(2, &[(1, [0; 3]), (2, [0; 3]), (3, [0; 3]), (4, [0; 3])]);
+		let report = compare(&voxelized, &reference);
+
+		assert_eq!(report.true_positives, 2);
+		assert_eq!(report.false_positives, 1);
+		assert_eq!(report.false_negatives, 2);
+		assert_eq!(report.iou, 0.4);
+	}
+
+	#[test]
+	fn compare_measures_the_attribute_error_of_shared_voxels() {
+		let voxelized = grid(2, &[(0, [255, 0, 51]), (1, [0; 3])]);
+		let reference = grid(2, &[(0, [0, 0, 0])]);
+		let report = compare(&voxelized, &reference);
+
+		assert_eq!(report.albedo_error, [1.0, 0.0, 0.2]);
+		// Normals span [-1, 1], twice the range of the colors
+		assert_eq!(report.normal_error, [2.0, 0.0, 0.4]);
+	}
+
+	#[test]
+	fn empty_grids_match() {
+		let report = compare(&grid(2, &[]), &grid(2, &[]));
+		assert_eq!(report.iou, 1.0);
+		assert_eq!(report.albedo_error, [0.0; 3]);
+	}
+}
@@ -0,0 +1,402 @@
+use crate::capabilities::GlCapabilities;
+use crate::renderer::{ConservativeMode, Renderer, VoxelizationMode};
+use crate::scene::camera::Camera;
+use crate::scene::description::load_scene;
+use crate::textures::VolumeStorage;
+use failure::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::Path;
+
+#[derive(Deserialize)]
+pub struct BenchmarkConfig {
+	pub scenes: Vec<String>,
+	pub resolutions: Vec<usize>,
+	#[serde(default = "default_storages")]
+	pub storages: Vec<VolumeStorage>,
+	pub modes: Vec<VoxelizationMode>,
+	#[serde(default = "default_conservative")]
+	pub conservative: Vec<ConservativeMode>,
+	pub cutoffs: Vec<f32>,
+	// Voxelize dense volumes into the fragment list, to count the fragments of every method
+	#[serde(default)]
+	pub fragment_list: bool,
+	// Voxelize the static primitives of dense volumes once, so only the dynamic ones are timed
+	#[serde(default)]
+	pub static_split: bool,
+	// Without it, only the frames where something changed would voxelize
+	#[serde(default = "default_force_revoxelize")]
+	pub force_revoxelize: bool,
+	// Turned off to time the voxelizers without the normal map fetches
+	#[serde(default = "default_normal_mapping")]
+	pub normal_mapping: bool,
+	#[serde(default = "default_warmup_frames")]
+	pub warmup_frames: usize,
+	#[serde(default = "default_frames")]
+	pub frames: usize,
+	pub output: String,
+}
+
+#[derive(Clone)]
+pub struct Configuration {
+	pub scene: String,
+	pub resolution: usize,
+	pub storage: VolumeStorage,
+	pub mode: VoxelizationMode,
+	pub conservative: ConservativeMode,
+	pub cutoff: f32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Statistics {
+	pub mean: f32,
+	pub median: f32,
+	pub p95: f32,
+	pub stddev: f32,
+}
+
+impl Statistics {
+	pub fn from_samples(samples: &[f32]) -> Statistics {
+		if samples.is_empty() {
+			return Statistics {
+				mean: 0.0,
+				median: 0.0,
+				p95: 0.0,
+				stddev: 0.0,
+			};
+		}
+
+		let mut sorted = samples.to_vec();
+		sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+		let count = sorted.len() as f32;
+		let mean = sorted.iter().sum::<f32>() / count;
+		let variance = sorted.iter().map(|s| (s - mean) * (s - mean)).sum::<f32>() / count;
+
+		let middle = sorted.len() / 2;
+		let median = if sorted.len() % 2 == 0 {
+			(sorted[middle - 1] + sorted[middle]) * 0.5
+		} else {
+			sorted[middle]
+		};
+
+		let p95_index = ((sorted.len() - 1) as f32 * 0.95).round() as usize;
+
+		Statistics {
+			mean,
+			median,
+			p95: sorted[p95_index],
+			stddev: variance.sqrt(),
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct Record {
+	scene: String,
+	resolution: usize,
+	storage: &'static str,
+	mode: &'static str,
+	conservative: &'static str,
+	cutoff: f32,
+	pass: String,
+	samples: usize,
+	mean_ms: f32,
+	median_ms: f32,
+	p95_ms: f32,
+	stddev_ms: f32,
+	large_triangles: u32,
+	small_triangles: u32,
+	fragments: u32,
+	occupied_voxels: u32,
+	list_fragments: Option<usize>,
+	gs_invocations: Option<u64>,
+	fs_invocations: Option<u64>,
+	cs_invocations: Option<u64>,
+}
+
+pub enum Progress {
+	Running,
+	Finished,
+}
+
+pub struct Benchmark {
+	config: BenchmarkConfig,
+	configurations: Vec<Configuration>,
+	current: usize,
+	frame: usize,
+	loaded_scene: Option<String>,
+	records: Vec<Record>,
+}
+
+impl Benchmark {
+	pub fn load<P: AsRef<Path>>(path: P, capabilities: &GlCapabilities) -> Result<Benchmark, Error> {
+		let path = path.as_ref();
+		let source = fs::read_to_string(path)
+			.map_err(|e| format_err!("Couldn't read benchmark '{}': {}", path.display(), e))?;
+
+		let config: BenchmarkConfig = ron::de::from_str(&source)
+			.map_err(|e| format_err!("Couldn't parse benchmark '{}': {}", path.display(), e))?;
+
+		Ok(Benchmark::new(config, capabilities))
+	}
+
+	pub fn new(config: BenchmarkConfig, capabilities: &GlCapabilities) -> Benchmark {
+		// Scenes are the outermost loop so each one is only loaded once
+		let mut configurations = Vec::new();
+		let conservative_modes: Vec<ConservativeMode> = config
+			.conservative
+			.iter()
+			.cloned()
+			.filter(|mode| {
+				let supported = mode.is_supported(capabilities);
+				if !supported {
+					println!("Skipping unsupported conservative mode '{}'", mode.name());
+				}
+				supported
+			})
+			.collect();
+		let modes: Vec<VoxelizationMode> = config
+			.modes
+			.iter()
+			.cloned()
+			.filter(|mode| {
+				let supported = capabilities.supports(mode.feature());
+				if !supported {
+					println!("Skipping the unsupported {} voxelizer", mode.name());
+				}
+				supported
+			})
+			.collect();
+
+		for scene in &config.scenes {
+			for &resolution in &config.resolutions {
+				for &storage in &config.storages {
+					for &mode in &modes {
+						for &conservative in &conservative_modes {
+							for &cutoff in &config.cutoffs {
+								configurations.push(Configuration {
+									scene: scene.clone(),
+									resolution,
+									storage,
+									mode,
+									conservative,
+									cutoff,
+								});
+							}
+						}
+					}
+				}
+			}
+		}
+
+		Benchmark {
+			config,
+			configurations,
+			current: 0,
+			frame: 0,
+			loaded_scene: None,
+			records: Vec::new(),
+		}
+	}
+
+	// Must be called once per frame, before rendering it
+	pub fn step(
+		&mut self,
+		renderer: &mut Renderer,
+		camera: &mut Camera,
+		scene_name: &mut String,
+	) -> Result<Progress, Error> {
+		let measure_end = self.config.warmup_frames + self.config.frames;
+
+		if self.frame == measure_end {
+			// The last frames are still in flight, wait for them so every configuration gets the
+			// same number of samples
+			renderer.timer_mut().flush();
+			self.collect(renderer, scene_name);
+			self.current += 1;
+			self.frame = 0;
+		}
+
+		let configuration = match self.configurations.get(self.current) {
+			Some(configuration) => configuration.clone(),
+			None => return Ok(Progress::Finished),
+		};
+
+		if self.frame == 0 {
+			if self.loaded_scene.as_ref() != Some(&configuration.scene) {
+				*scene_name = load_scene(renderer, camera, &configuration.scene)?;
+				self.loaded_scene = Some(configuration.scene.clone());
+			}
+
+			renderer.set_volume(configuration.storage, configuration.resolution);
+			renderer.voxelization_mode = configuration.mode;
+			renderer.set_conservative_mode(configuration.conservative);
+			renderer.cutoff = configuration.cutoff;
+			renderer.fragment_output = self.config.fragment_list;
+			renderer.static_split = self.config.static_split;
+			renderer.force_revoxelize = self.config.force_revoxelize;
+			renderer.normal_mapping = self.config.normal_mapping;
+
+			println!("Benchmark {}", self.status());
+		}
+
+		if self.frame == self.config.warmup_frames {
+			renderer.timer_mut().reset();
+		}
+
+		// Reading the counters back stalls, so it only happens on the last warmup frame
+		renderer.collect_statistics = self.frame + 1 == self.config.warmup_frames.max(1);
+
+		self.frame += 1;
+
+		Ok(Progress::Running)
+	}
+
+	fn collect(&mut self, renderer: &Renderer, scene_name: &str) {
+		let configuration = &self.configurations[self.current];
+		let timer = renderer.timer();
+		let counters = renderer.statistics().cloned().unwrap_or_default();
+		let pipeline = counters.pipeline;
+
+		for label in timer.labels() {
+			let samples = timer.samples(label);
+			if samples.is_empty() {
+				continue;
+			}
+
+			let statistics = Statistics::from_samples(&samples);
+			self.records.push(Record {
+				scene: scene_name.to_owned(),
+				resolution: configuration.resolution,
+				storage: configuration.storage.name(),
+				mode: configuration.mode.name(),
+				conservative: configuration.conservative.name(),
+				cutoff: configuration.cutoff,
+				pass: label.to_owned(),
+				samples: samples.len(),
+				mean_ms: statistics.mean,
+				median_ms: statistics.median,
+				p95_ms: statistics.p95,
+				stddev_ms: statistics.stddev,
+				large_triangles: counters.large_triangles,
+				small_triangles: counters.small_triangles,
+				fragments: counters.fragments,
+				occupied_voxels: counters.occupied_voxels,
+				list_fragments: renderer.fragment_count(),
+				gs_invocations: pipeline.map(|p| p.geometry_shader_invocations),
+				fs_invocations: pipeline.map(|p| p.fragment_shader_invocations),
+				cs_invocations: pipeline.map(|p| p.compute_shader_invocations),
+			});
+		}
+	}
+
+	pub fn status(&self) -> String {
+		match self.configurations.get(self.current) {
+			Some(configuration) => format!(
+				"{}/{}: {} {}^3 {} {} conservative={} cutoff={:.2}",
+				self.current + 1,
+				self.configurations.len(),
+				configuration.scene,
+				configuration.resolution,
+				configuration.storage.name(),
+				configuration.mode.name(),
+				configuration.conservative.name(),
+				configuration.cutoff
+			),
+			None => format!("finished {} configurations", self.configurations.len()),
+		}
+	}
+
+	pub fn save(&self, capabilities: &GlCapabilities) -> Result<(), Error> {
+		let path = Path::new(&self.config.output);
+
+		if path
+			.extension()
+			.map_or(false, |extension| extension == "json")
+		{
+			let file = File::create(path)?;
+			serde_json::to_writer_pretty(file, &self.records)?;
+		} else {
+			let mut writer = csv::Writer::from_path(path)?;
+			for record in &self.records {
+				writer.serialize(record)?;
+			}
+			writer.flush()?;
+		}
+
+		println!("Benchmark results written to '{}'", path.display());
+
+		let capabilities_path = capabilities.save_next_to(path)?;
+		println!(
+			"GPU capabilities written to '{}'",
+			capabilities_path.display()
+		);
+
+		Ok(())
+	}
+}
+
+fn default_conservative() -> Vec<ConservativeMode> {
+	vec![ConservativeMode::Nv, ConservativeMode::GeometryShader]
+}
+
+fn default_storages() -> Vec<VolumeStorage> {
+	vec![VolumeStorage::Dense]
+}
+
+fn default_warmup_frames() -> usize {
+	60
+}
+
+fn default_frames() -> usize {
+	300
+}
+
+fn default_force_revoxelize() -> bool {
+	true
+}
+
+fn default_normal_mapping() -> bool {
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn statistics_of_no_samples_are_zero() {
+		let statistics = Statistics::from_samples(&[]);
+		assert_eq!(statistics.mean, 0.0);
+		assert_eq!(statistics.median, 0.0);
+		assert_eq!(statistics.p95, 0.0);
+		assert_eq!(statistics.stddev, 0.0);
+	}
+
+	#[test]
+	fn statistics_of_an_odd_count() {
+		let statistics = Statistics::from_samples(&[5.0, 1.0, 3.0]);
+		assert_eq!(statistics.mean, 3.0);
+		assert_eq!(statistics.median, 3.0);
+		assert_eq!(statistics.p95, 5.0);
+		assert!((statistics.stddev - (8.0f32 / 3.0).sqrt()).abs() < 1e-6);
+	}
+
+	#[test]
+	fn statistics_of_an_even_count() {
+		let statistics = Statistics::from_samples(&[4.0, 2.0, 8.0, 6.0]);
+		assert_eq!(statistics.mean, 5.0);
+		assert_eq!(statistics.median, 5.0);
+		assert_eq!(statistics.p95, 8.0);
+		assert!((statistics.stddev - 5.0f32.sqrt()).abs() < 1e-6);
+	}
+
+	#[test]
+	fn p95_ignores_the_slowest_twentieth() {
+		let samples: Vec<f32> = (1..=100).map(|i| i as f32).collect();
+		let statistics = Statistics::from_samples(&samples);
+		assert_eq!(statistics.median, 50.5);
+		assert_eq!(statistics.p95, 95.0);
+	}
+}
@@ -0,0 +1,384 @@
+use failure::{format_err, Error};
+use gl;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Feature {
+	Core,
+	FragmentVoxelization,
+	HybridVoxelization,
+	ComputeVoxelization,
+	SparseOctree,
+	VoxelizationStatistics,
+	PipelineStatistics,
+	NvConservative,
+	IntelConservative,
+}
+
+struct Requirement {
+	feature: Feature,
+	name: &'static str,
+	// The renderer can't start without it, otherwise the feature is just disabled
+	mandatory: bool,
+	version: (i32, i32),
+	extensions: &'static [&'static str],
+	limits: &'static [(&'static str, i32)],
+}
+
+const REQUIREMENTS: &[Requirement] = &[
+	Requirement {
+		feature: Feature::Core,
+		name: "core",
+		mandatory: true,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_3D_TEXTURE_SIZE", 256),
+			("MAX_COMBINED_IMAGE_UNIFORMS", 4),
+			("MAX_IMAGE_UNITS", 5),
+			("MAX_COMPUTE_WORK_GROUP_INVOCATIONS", 512),
+		],
+	},
+	Requirement {
+		feature: Feature::FragmentVoxelization,
+		name: "fragment_voxelization",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_FRAGMENT_IMAGE_UNIFORMS", 3),
+			("MAX_FRAGMENT_SHADER_STORAGE_BLOCKS", 1),
+		],
+	},
+	Requirement {
+		feature: Feature::HybridVoxelization,
+		name: "hybrid_voxelization",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_GEOMETRY_IMAGE_UNIFORMS", 5),
+			("MAX_GEOMETRY_ATOMIC_COUNTER_BUFFERS", 1),
+			("MAX_GEOMETRY_TEXTURE_IMAGE_UNITS", 1),
+			("MAX_GEOMETRY_SHADER_STORAGE_BLOCKS", 1),
+			("MAX_FRAGMENT_SHADER_STORAGE_BLOCKS", 1),
+		],
+	},
+	Requirement {
+		feature: Feature::ComputeVoxelization,
+		name: "compute_voxelization",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_COMPUTE_SHADER_STORAGE_BLOCKS", 3),
+			("MAX_COMPUTE_IMAGE_UNIFORMS", 3),
+		],
+	},
+	Requirement {
+		feature: Feature::SparseOctree,
+		name: "sparse_octree",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_FRAGMENT_SHADER_STORAGE_BLOCKS", 1),
+			("MAX_COMPUTE_SHADER_STORAGE_BLOCKS", 2),
+			("MAX_COMPUTE_IMAGE_UNIFORMS", 2),
+			("MAX_SHADER_STORAGE_BLOCK_SIZE", 1 << 27),
+		],
+	},
+	Requirement {
+		feature: Feature::VoxelizationStatistics,
+		name: "voxelization_statistics",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &[],
+		limits: &[
+			("MAX_ATOMIC_COUNTER_BUFFER_BINDINGS", 2),
+			("MAX_GEOMETRY_ATOMIC_COUNTER_BUFFERS", 2),
+			("MAX_FRAGMENT_ATOMIC_COUNTER_BUFFERS", 1),
+			("MAX_COMPUTE_ATOMIC_COUNTER_BUFFERS", 1),
+		],
+	},
+	Requirement {
+		feature: Feature::PipelineStatistics,
+		name: "pipeline_statistics",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &["GL_ARB_pipeline_statistics_query"],
+		limits: &[],
+	},
+	Requirement {
+		feature: Feature::NvConservative,
+		name: "nv_conservative_raster",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &["GL_NV_conservative_raster"],
+		limits: &[],
+	},
+	Requirement {
+		feature: Feature::IntelConservative,
+		name: "intel_conservative_rasterization",
+		mandatory: false,
+		version: (4, 5),
+		extensions: &["GL_INTEL_conservative_rasterization"],
+		limits: &[],
+	},
+];
+
+const PROBED_LIMITS: &[(&str, u32)] = &[
+	("MAX_3D_TEXTURE_SIZE", gl::MAX_3D_TEXTURE_SIZE),
+	("MAX_IMAGE_UNITS", gl::MAX_IMAGE_UNITS),
+	(
+		"MAX_COMBINED_IMAGE_UNIFORMS",
+		gl::MAX_COMBINED_IMAGE_UNIFORMS,
+	),
+	(
+		"MAX_FRAGMENT_IMAGE_UNIFORMS",
+		gl::MAX_FRAGMENT_IMAGE_UNIFORMS,
+	),
+	(
+		"MAX_GEOMETRY_IMAGE_UNIFORMS",
+		gl::MAX_GEOMETRY_IMAGE_UNIFORMS,
+	),
+	("MAX_COMPUTE_IMAGE_UNIFORMS", gl::MAX_COMPUTE_IMAGE_UNIFORMS),
+	(
+		"MAX_GEOMETRY_TEXTURE_IMAGE_UNITS",
+		gl::MAX_GEOMETRY_TEXTURE_IMAGE_UNITS,
+	),
+	(
+		"MAX_ATOMIC_COUNTER_BUFFER_BINDINGS",
+		gl::MAX_ATOMIC_COUNTER_BUFFER_BINDINGS,
+	),
+	(
+		"MAX_ATOMIC_COUNTER_BUFFER_SIZE",
+		gl::MAX_ATOMIC_COUNTER_BUFFER_SIZE,
+	),
+	(
+		"MAX_GEOMETRY_ATOMIC_COUNTER_BUFFERS",
+		gl::MAX_GEOMETRY_ATOMIC_COUNTER_BUFFERS,
+	),
+	(
+		"MAX_FRAGMENT_ATOMIC_COUNTER_BUFFERS",
+		gl::MAX_FRAGMENT_ATOMIC_COUNTER_BUFFERS,
+	),
+	(
+		"MAX_COMPUTE_ATOMIC_COUNTER_BUFFERS",
+		gl::MAX_COMPUTE_ATOMIC_COUNTER_BUFFERS,
+	),
+	(
+		"MAX_COMPUTE_SHADER_STORAGE_BLOCKS",
+		gl::MAX_COMPUTE_SHADER_STORAGE_BLOCKS,
+	),
+	(
+		"MAX_GEOMETRY_SHADER_STORAGE_BLOCKS",
+		gl::MAX_GEOMETRY_SHADER_STORAGE_BLOCKS,
+	),
+	(
+		"MAX_FRAGMENT_SHADER_STORAGE_BLOCKS",
+		gl::MAX_FRAGMENT_SHADER_STORAGE_BLOCKS,
+	),
+	(
+		"MAX_SHADER_STORAGE_BLOCK_SIZE",
+		gl::MAX_SHADER_STORAGE_BLOCK_SIZE,
+	),
+	(
+		"MAX_SHADER_STORAGE_BUFFER_BINDINGS",
+		gl::MAX_SHADER_STORAGE_BUFFER_BINDINGS,
+	),
+	(
+		"MAX_COMPUTE_WORK_GROUP_INVOCATIONS",
+		gl::MAX_COMPUTE_WORK_GROUP_INVOCATIONS,
+	),
+];
+
+#[derive(Serialize)]
+pub struct FeatureStatus {
+	pub name: &'static str,
+	pub available: bool,
+	pub missing: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct GlCapabilities {
+	pub vendor: String,
+	pub renderer: String,
+	pub version: String,
+	pub glsl_version: String,
+	pub major: i32,
+	pub minor: i32,
+	pub limits: BTreeMap<&'static str, i32>,
+	pub max_compute_work_group_size: [i32; 3],
+	pub extensions: Vec<String>,
+	pub features: Vec<FeatureStatus>,
+}
+
+impl GlCapabilities {
+	// Needs a current context with the gl function pointers loaded
+	pub fn probe() -> GlCapabilities {
+		let (mut major, mut minor) = (0, 0);
+		let mut num_extensions = 0;
+		let mut max_compute_work_group_size = [0; 3];
+		let mut limits = BTreeMap::new();
+
+		unsafe {
+			gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+			gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+			gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut num_extensions);
+
+			for (i, size) in max_compute_work_group_size.iter_mut().enumerate() {
+				gl::GetIntegeri_v(gl::MAX_COMPUTE_WORK_GROUP_SIZE, i as u32, size);
+			}
+
+			for (name, parameter) in PROBED_LIMITS {
+				let mut value = 0;
+				gl::GetIntegerv(*parameter, &mut value);
+				limits.insert(*name, value);
+			}
+		}
+
+		let extensions = (0..num_extensions)
+			.map(|i| unsafe { gl_string(gl::GetStringi(gl::EXTENSIONS, i as u32)) })
+			.collect();
+
+		let mut capabilities = unsafe {
+			GlCapabilities {
+				vendor: gl_string(gl::GetString(gl::VENDOR)),
+				renderer: gl_string(gl::GetString(gl::RENDERER)),
+				version: gl_string(gl::GetString(gl::VERSION)),
+				glsl_version: gl_string(gl::GetString(gl::SHADING_LANGUAGE_VERSION)),
+				major,
+				minor,
+				limits,
+				max_compute_work_group_size,
+				extensions,
+				features: Vec::new(),
+			}
+		};
+
+		capabilities.features = REQUIREMENTS
+			.iter()
+			.map(|requirement| {
+				let missing = capabilities.missing(requirement);
+				FeatureStatus {
+					name: requirement.name,
+					available: missing.is_empty(),
+					missing,
+				}
+			})
+			.collect();
+
+		capabilities
+	}
+
+	fn missing(&self, requirement: &Requirement) -> Vec<String> {
+		let mut missing = Vec::new();
+
+		if (self.major, self.minor) < requirement.version {
+			missing.push(format!(
+				"OpenGL {}.{} (context is {}.{})",
+				requirement.version.0, requirement.version.1, self.major, self.minor
+			));
+		}
+
+		for extension in requirement.extensions {
+			if !self.has_extension(extension) {
+				missing.push(extension.to_string());
+			}
+		}
+
+		for (name, minimum) in requirement.limits {
+			let value = self.limits.get(name).cloned().unwrap_or(0);
+			if value < *minimum {
+				missing.push(format!("{} >= {} (got {})", name, minimum, value));
+			}
+		}
+
+		missing
+	}
+
+	pub fn has_extension(&self, extension: &str) -> bool {
+		self.extensions.iter().any(|name| name == extension)
+	}
+
+	pub fn supports(&self, feature: Feature) -> bool {
+		REQUIREMENTS
+			.iter()
+			.zip(self.features.iter())
+			.any(|(requirement, status)| requirement.feature == feature && status.available)
+	}
+
+	// Fails with everything a mandatory feature is missing
+	pub fn check(&self) -> Result<(), Error> {
+		let problems: Vec<String> = REQUIREMENTS
+			.iter()
+			.zip(self.features.iter())
+			.filter(|(requirement, status)| requirement.mandatory && !status.available)
+			.flat_map(|(_, status)| status.missing.iter().cloned())
+			.collect();
+
+		if problems.is_empty() {
+			Ok(())
+		} else {
+			Err(format_err!(
+				"{} ({}) can't run the renderer, it is missing:\n  {}",
+				self.renderer,
+				self.version,
+				problems.join("\n  ")
+			))
+		}
+	}
+
+	pub fn print_report(&self) {
+		println!("OpenGL vendor : {}", self.vendor);
+		println!("OpenGL renderer : {}", self.renderer);
+		println!("OpenGL version : {}", self.version);
+		println!("GLSL version : {}", self.glsl_version);
+		for (name, value) in &self.limits {
+			println!("{} : {}", name, value);
+		}
+		println!(
+			"MAX_COMPUTE_WORK_GROUP_SIZE : ({}, {}, {})",
+			self.max_compute_work_group_size[0],
+			self.max_compute_work_group_size[1],
+			self.max_compute_work_group_size[2]
+		);
+
+		println!("FEATURES");
+		println!("--------");
+		for status in &self.features {
+			if status.available {
+				println!("{} : available", status.name);
+			} else {
+				println!(
+					"{} : disabled, missing {}",
+					status.name,
+					status.missing.join(", ")
+				);
+			}
+		}
+	}
+
+	// Writes `<results>.capabilities.json` next to a results file
+	pub fn save_next_to<P: AsRef<Path>>(&self, results: P) -> Result<PathBuf, Error> {
+		let path = results.as_ref().with_extension("capabilities.json");
+		serde_json::to_writer_pretty(File::create(&path)?, self)?;
+
+		Ok(path)
+	}
+}
+
+unsafe fn gl_string(string: *const u8) -> String {
+	if string.is_null() {
+		return String::new();
+	}
+
+	CStr::from_ptr(string as *const _)
+		.to_string_lossy()
+		.into_owned()
+}
@@ -0,0 +1,485 @@
+use crate::renderer_utils::*;
+use crate::shader_layout::*;
+use crate::textures::Volume;
+use gl;
+use nalgebra_glm as glm;
+use std::ptr;
+
+// Matches the sampler array of pbr.frag
+pub const MAX_CASCADES: usize = 4;
+// The cascades move in steps of this many voxels, so the first mips of the radiance stay aligned
+// and the camera has to move a bit before anything is revoxelized
+const SNAP_VOXELS: i32 = 4;
+// Only the mips whose texels the cascades move by whole texels, log2(SNAP_VOXELS) + 1. The coarser
+// ones would average across the seam of the toroidal addressing. Matches pbr.frag
+const CASCADE_MIPS: usize = 3;
+// The smallest resolution the cascades can mipmap
+pub const MIN_CLIPMAP_RESOLUTION: usize = 1 << (CASCADE_MIPS - 1);
+
+struct Cascade {
+	albedo: u32,
+	normal: u32,
+	emission: u32,
+	radiance: u32,
+	// World voxel coordinate of the minimum corner, unknown until the first update
+	origin: Option<[i32; 3]>,
+}
+
+impl Cascade {
+	fn new(resolution: usize) -> Cascade {
+		Cascade {
+			albedo: allocate_cascade_texture(resolution, 1),
+			normal: allocate_cascade_texture(resolution, 1),
+			emission: allocate_cascade_texture(resolution, 1),
+			radiance: allocate_cascade_texture(resolution, CASCADE_MIPS),
+			origin: None,
+		}
+	}
+}
+
+impl Drop for Cascade {
+	fn drop(&mut self) {
+		unsafe {
+			let textures = [self.albedo, self.normal, self.emission, self.radiance];
+			gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+		}
+	}
+}
+
+// The voxels of a cascade that have to be voxelized again after it moved
+pub struct CascadeUpdate {
+	pub cascade: usize,
+	pub regions: Vec<VoxelRegion>,
+}
+
+// Nested volumes of the same resolution centered on the camera, each one twice as large as the
+// previous one. They are stored toroidally: a world voxel always lands on the texel given by its
+// coordinate modulo the resolution, so when a cascade moves only the slabs it uncovers change
+pub struct Clipmap {
+	resolution: usize,
+	cascades: Vec<Cascade>,
+	// Extent of the finest cascade
+	base_extent: f32,
+}
+
+impl Clipmap {
+	pub fn new(resolution: usize, cascade_count: usize) -> Clipmap {
+		assert!(
+			cascade_count >= 1 && cascade_count <= MAX_CASCADES,
+			"The clipmap needs between 1 and {} cascades, got {}",
+			MAX_CASCADES,
+			cascade_count
+		);
+		assert!(
+			resolution.is_power_of_two() && resolution >= MIN_CLIPMAP_RESOLUTION,
+			"The clipmap needs a power of two resolution of at least {}, got {}",
+			MIN_CLIPMAP_RESOLUTION,
+			resolution
+		);
+
+		Clipmap {
+			resolution,
+			cascades: (0..cascade_count)
+				.map(|_| Cascade::new(resolution))
+				.collect(),
+			base_extent: 0.0,
+		}
+	}
+
+	pub fn cascade_count(&self) -> usize {
+		self.cascades.len()
+	}
+
+	pub fn memory_bytes(&self) -> usize {
+		self.cascades.len() * Volume::dense_memory_bytes(self.resolution)
+	}
+
+	fn voxel_size(&self, cascade: usize) -> f32 {
+		self.extent(cascade) / self.resolution as f32
+	}
+
+	pub fn extent(&self, cascade: usize) -> f32 {
+		self.base_extent * (1 << cascade) as f32
+	}
+
+	pub fn min_corner(&self, cascade: usize) -> glm::Vec3 {
+		let origin = self.cascades[cascade].origin.unwrap_or([0; 3]);
+		glm::vec3(origin[0] as f32, origin[1] as f32, origin[2] as f32) * self.voxel_size(cascade)
+	}
+
+	pub fn center(&self, cascade: usize) -> glm::Vec3 {
+		self.min_corner(cascade) + glm::vec3(1.0, 1.0, 1.0) * (self.extent(cascade) * 0.5)
+	}
+
+	// Texel of the minimum corner, where the toroidal addressing starts
+	pub fn offset(&self, cascade: usize) -> [i32; 3] {
+		let origin = self.cascades[cascade].origin.unwrap_or([0; 3]);
+		let resolution = self.resolution as i32;
+		[
+			origin[0].rem_euclid(resolution),
+			origin[1].rem_euclid(resolution),
+			origin[2].rem_euclid(resolution),
+		]
+	}
+
+	// Centers the cascades on `center` and clears the voxels they uncovered, which the caller has
+	// to voxelize again. Without `incremental`, or when the finest cascade changed size, every
+	// cascade is cleared entirely
+	pub fn update(
+		&mut self,
+		center: &glm::Vec3,
+		base_extent: f32,
+		incremental: bool,
+	) -> Vec<CascadeUpdate> {
+		if base_extent != self.base_extent {
+			self.base_extent = base_extent;
+			for cascade in &mut self.cascades {
+				cascade.origin = None;
+			}
+		}
+
+		let resolution = self.resolution as i32;
+		let mut updates = Vec::new();
+		for i in 0..self.cascades.len() {
+			let voxel_size = self.voxel_size(i);
+			let mut origin = [0; 3];
+			for axis in 0..3 {
+				let voxel = (center[axis] / voxel_size).floor() as i32 - resolution / 2;
+				origin[axis] = voxel.div_euclid(SNAP_VOXELS) * SNAP_VOXELS;
+			}
+
+			let previous = self.cascades[i].origin;
+			self.cascades[i].origin = Some(origin);
+
+			let mut regions = match previous {
+				Some(previous) if incremental => exposed_regions(&previous, &origin, resolution),
+				_ => vec![VoxelRegion::whole(self.resolution)],
+			};
+			if regions.is_empty() {
+				continue;
+			}
+
+			let offset = self.offset(i);
+			for region in &mut regions {
+				region.offset = offset;
+				self.clear_region(i, region);
+			}
+			updates.push(CascadeUpdate {
+				cascade: i,
+				regions,
+			});
+		}
+
+		updates
+	}
+
+	// Clears the voxels of every cascade that overlap the world space box, which the caller has to
+	// voxelize again. Used for the primitives that moved, at their old and at their new place
+	pub fn clear_box(&self, min: &glm::Vec3, max: &glm::Vec3) -> Vec<CascadeUpdate> {
+		let mut updates = Vec::new();
+		for i in 0..self.cascades.len() {
+			let origin = match self.cascades[i].origin {
+				Some(origin) => origin,
+				None => continue,
+			};
+
+			let region = match box_region(min, max, &origin, self.voxel_size(i), self.resolution) {
+				Some(mut region) => {
+					region.offset = self.offset(i);
+					region
+				}
+				None => continue,
+			};
+			self.clear_region(i, &region);
+			updates.push(CascadeUpdate {
+				cascade: i,
+				regions: vec![region],
+			});
+		}
+
+		updates
+	}
+
+	fn clear_region(&self, cascade: usize, region: &VoxelRegion) {
+		let resolution = self.resolution as i32;
+		let ranges: Vec<_> = (0..3)
+			.map(|axis| {
+				wrapped_ranges(
+					region.min[axis],
+					region.max[axis],
+					region.offset[axis],
+					resolution,
+				)
+			})
+			.collect();
+
+		let cascade = &self.cascades[cascade];
+		for texture in &[cascade.albedo, cascade.normal, cascade.emission] {
+			for (x, width) in &ranges[0] {
+				for (y, height) in &ranges[1] {
+					for (z, depth) in &ranges[2] {
+						unsafe {
+							gl::ClearTexSubImage(
+								*texture,
+								0,
+								*x,
+								*y,
+								*z,
+								*width,
+								*height,
+								*depth,
+								gl::RGBA,
+								gl::UNSIGNED_BYTE,
+								ptr::null(),
+							);
+						}
+					}
+				}
+			}
+		}
+	}
+
+	// The voxelizers write the voxels at their position in the cascade, offset to the texel
+	pub fn voxel_target(&self, cascade: usize, region: &VoxelRegion) -> VoxelTarget {
+		let extent = self.extent(cascade);
+		let scale = glm::vec3(extent, extent, extent);
+		let textures = &self.cascades[cascade];
+
+		VoxelTarget {
+			albedo: textures.albedo,
+			normal: textures.normal,
+			emission: textures.emission,
+			resolution: [self.resolution as i32; 3],
+			// Without margins, so a world voxel maps to the same voxel wherever the cascade is
+			pv: box_pv(&self.center(cascade), &scale, &glm::vec3(0.0, 0.0, 0.0)),
+			region: *region,
+		}
+	}
+
+	// The radiance is injected again every frame, the voxels that became empty must not keep theirs
+	pub fn clear_radiance(&self) {
+		for cascade in &self.cascades {
+			unsafe {
+				gl::ClearTexImage(
+					cascade.radiance,
+					0,
+					gl::RGBA,
+					gl::UNSIGNED_BYTE,
+					ptr::null(),
+				);
+			}
+		}
+	}
+
+	pub fn bind_for_injection(&self, cascade: usize) {
+		let cascade = &self.cascades[cascade];
+		unsafe {
+			gl::BindTextureUnit(ALBEDO_UNIT, cascade.albedo);
+			gl::BindTextureUnit(NORMAL_UNIT, cascade.normal);
+			gl::BindTextureUnit(EMISSION_UNIT, cascade.emission);
+			gl::BindImageTexture(
+				RADIANCE_UNIT,
+				cascade.radiance,
+				0,
+				gl::TRUE,
+				0,
+				gl::READ_WRITE,
+				gl::RGBA8,
+			);
+		}
+	}
+
+	pub fn generate_mipmaps(&self) {
+		for cascade in &self.cascades {
+			unsafe {
+				gl::GenerateTextureMipmap(cascade.radiance);
+			}
+		}
+	}
+
+	// One unit per cascade, starting at `first_unit`
+	pub fn bind_for_tracing(&self, first_unit: u32) {
+		for (i, cascade) in self.cascades.iter().enumerate() {
+			unsafe {
+				gl::BindTextureUnit(first_unit + i as u32, cascade.radiance);
+			}
+		}
+	}
+}
+
+// The slabs uncovered by moving the minimum corner from `previous` to `origin`, in voxels of the
+// cascade at its new position
+fn exposed_regions(previous: &[i32; 3], origin: &[i32; 3], resolution: i32) -> Vec<VoxelRegion> {
+	let mut regions = Vec::new();
+	for axis in 0..3 {
+		let delta = origin[axis] - previous[axis];
+		if delta.abs() >= resolution {
+			return vec![VoxelRegion::whole(resolution as usize)];
+		}
+		if delta == 0 {
+			continue;
+		}
+
+		let mut region = VoxelRegion::whole(resolution as usize);
+		if delta > 0 {
+			region.min[axis] = resolution - delta;
+		} else {
+			region.max[axis] = -delta;
+		}
+		regions.push(region);
+	}
+
+	regions
+}
+
+// The voxels of a cascade with its minimum corner at `origin` that overlap the world space box, with
+// a voxel of margin for the conservative voxelizers. None when the box is outside of the cascade
+fn box_region(
+	min: &glm::Vec3,
+	max: &glm::Vec3,
+	origin: &[i32; 3],
+	voxel_size: f32,
+	resolution: usize,
+) -> Option<VoxelRegion> {
+	let mut region = VoxelRegion::whole(resolution);
+	for axis in 0..3 {
+		let low = (min[axis] / voxel_size).floor() as i32 - 1 - origin[axis];
+		let high = (max[axis] / voxel_size).ceil() as i32 + 1 - origin[axis];
+		region.min[axis] = low.max(0);
+		region.max[axis] = high.min(resolution as i32);
+		if region.min[axis] >= region.max[axis] {
+			return None;
+		}
+	}
+
+	Some(region)
+}
+
+// The texel ranges, as start and length, covered by the voxels `min..max` of one axis once they
+// are offset and wrapped around
+fn wrapped_ranges(min: i32, max: i32, offset: i32, resolution: i32) -> Vec<(i32, i32)> {
+	let length = max - min;
+	if length >= resolution {
+		return vec![(0, resolution)];
+	}
+
+	let start = (min + offset).rem_euclid(resolution);
+	if start + length <= resolution {
+		vec![(start, length)]
+	} else {
+		vec![
+			(start, resolution - start),
+			(0, start + length - resolution),
+		]
+	}
+}
+
+// Repeats so the toroidal addressing is left to the sampler
+fn allocate_cascade_texture(resolution: usize, mips: usize) -> u32 {
+	use gl::*;
+
+	let mut handle = 0;
+	let size = resolution as i32;
+	unsafe {
+		CreateTextures(TEXTURE_3D, 1, &mut handle);
+		TextureParameteri(handle, TEXTURE_WRAP_S, REPEAT as i32);
+		TextureParameteri(handle, TEXTURE_WRAP_T, REPEAT as i32);
+		TextureParameteri(handle, TEXTURE_WRAP_R, REPEAT as i32);
+		TextureParameteri(handle, TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as i32);
+		TextureParameteri(handle, TEXTURE_MAG_FILTER, LINEAR as i32);
+		TextureStorage3D(handle, mips as i32, RGBA8, size, size, size);
+		ClearTexImage(handle, 0, RGBA, UNSIGNED_BYTE, ptr::null());
+	}
+
+	handle
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn bounds(region: &VoxelRegion) -> ([i32; 3], [i32; 3]) {
+		(region.min, region.max)
+	}
+
+	#[test]
+	fn a_still_cascade_exposes_nothing() {
+		assert!(exposed_regions(&[3, -2, 5], &[3, -2, 5], 64).is_empty());
+	}
+
+	#[test]
+	fn moving_up_exposes_the_top_slab() {
+		let regions = exposed_regions(&[0, 0, 0], &[4, 0, 0], 64);
+		assert_eq!(regions.len(), 1);
+		assert_eq!(bounds(&regions[0]), ([60, 0, 0], [64, 64, 64]));
+	}
+
+	#[test]
+	fn moving_down_exposes_the_bottom_slab() {
+		let regions = exposed_regions(&[0, 10, 0], &[0, 6, 0], 64);
+		assert_eq!(regions.len(), 1);
+		assert_eq!(bounds(&regions[0]), ([0, 0, 0], [64, 4, 64]));
+	}
+
+	#[test]
+	fn moving_diagonally_exposes_a_slab_per_axis() {
+		let regions = exposed_regions(&[0, 0, 0], &[4, 0, -8], 64);
+		assert_eq!(regions.len(), 2);
+		assert_eq!(bounds(&regions[0]), ([60, 0, 0], [64, 64, 64]));
+		assert_eq!(bounds(&regions[1]), ([0, 0, 0], [64, 64, 8]));
+	}
+
+	#[test]
+	fn moving_a_whole_cascade_exposes_all_of_it() {
+		for &delta in &[64, -64, 100, -100] {
+			let regions = exposed_regions(&[0, 0, 0], &[4, delta, 0], 64);
+			assert_eq!(regions.len(), 1);
+			assert_eq!(bounds(&regions[0]), ([0, 0, 0], [64, 64, 64]));
+		}
+	}
+
+	#[test]
+	fn ranges_inside_the_texture_are_not_split() {
+		assert_eq!(wrapped_ranges(4, 12, 0, 64), vec![(4, 8)]);
+		assert_eq!(wrapped_ranges(4, 12, 50, 64), vec![(54, 8)]);
+	}
+
+	#[test]
+	fn ranges_past_the_edge_wrap_around() {
+		assert_eq!(wrapped_ranges(60, 64, 2, 64), vec![(62, 2), (0, 2)]);
+		assert_eq!(wrapped_ranges(0, 8, -4, 64), vec![(60, 4), (0, 4)]);
+	}
+
+	#[test]
+	fn offsets_wrap_any_number_of_times() {
+		assert_eq!(wrapped_ranges(0, 4, 130, 64), vec![(2, 4)]);
+		assert_eq!(wrapped_ranges(0, 4, -130, 64), vec![(62, 2), (0, 2)]);
+	}
+
+	#[test]
+	fn a_whole_axis_covers_the_texture_once() {
+		assert_eq!(wrapped_ranges(0, 64, 17, 64), vec![(0, 64)]);
+		assert_eq!(wrapped_ranges(-8, 70, 3, 64), vec![(0, 64)]);
+	}
+
+	#[test]
+	fn box_regions_are_clamped_to_the_cascade() {
+		let region = box_region(
+			&glm::vec3(1.5, -100.0, 0.0),
+			&glm::vec3(2.5, 100.0, 0.5),
+			&[0, 0, 0],
+			0.5,
+			64,
+		)
+		.unwrap();
+		assert_eq!(bounds(&region), ([2, 0, 0], [6, 64, 2]));
+		assert!(box_region(
+			&glm::vec3(100.0, 0.0, 0.0),
+			&glm::vec3(101.0, 1.0, 1.0),
+			&[0, 0, 0],
+			0.5,
+			64
+		)
+		.is_none());
+	}
+}
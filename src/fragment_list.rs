@@ -0,0 +1,222 @@
+use gl;
+use gl::types::*;
+use serde::Serialize;
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr;
+
+// Matches `FragmentList` in fragment_list.glsl: the appended count, the capacity and padding, then
+// one uvec4 per fragment
+const HEADER_SIZE: usize = 4 * size_of::<u32>();
+const FRAGMENT_SIZE: usize = 4 * size_of::<u32>();
+
+// A fragment read back from the list, with the attributes unpacked
+#[derive(Serialize)]
+pub struct VoxelFragment {
+	pub x: u32,
+	pub y: u32,
+	pub z: u32,
+	pub albedo_r: f32,
+	pub albedo_g: f32,
+	pub albedo_b: f32,
+	pub normal_x: f32,
+	pub normal_y: f32,
+	pub normal_z: f32,
+	pub emission_r: f32,
+	pub emission_g: f32,
+	pub emission_b: f32,
+}
+
+impl VoxelFragment {
+	fn unpack(packed: &[u32]) -> VoxelFragment {
+		let albedo = unpack_unorm(packed[1]);
+		// Normals are stored encoded to [0, 1]
+		let normal = unpack_unorm(packed[2]);
+		let emission = unpack_unorm(packed[3]);
+
+		VoxelFragment {
+			x: packed[0] & 0x3FF,
+			y: (packed[0] >> 10) & 0x3FF,
+			z: (packed[0] >> 20) & 0x3FF,
+			albedo_r: albedo[0],
+			albedo_g: albedo[1],
+			albedo_b: albedo[2],
+			normal_x: normal[0] * 2.0 - 1.0,
+			normal_y: normal[1] * 2.0 - 1.0,
+			normal_z: normal[2] * 2.0 - 1.0,
+			emission_r: emission[0],
+			emission_g: emission[1],
+			emission_b: emission[2],
+		}
+	}
+}
+
+pub struct FragmentList {
+	handle: u32,
+	capacity: usize,
+}
+
+impl FragmentList {
+	pub fn new(capacity: usize) -> FragmentList {
+		FragmentList {
+			handle: allocate_buffer(capacity),
+			capacity,
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	pub fn bind_unit(&self, unit: u32) {
+		unsafe {
+			gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, unit, self.handle);
+		}
+	}
+
+	// Empties the list, the capacity goes in the header so the shaders don't write past the end
+	pub fn clear(&self) {
+		let header = [0u32, self.capacity as u32, 0, 0];
+		unsafe {
+			gl::NamedBufferSubData(
+				self.handle,
+				0,
+				HEADER_SIZE as isize,
+				header.as_ptr() as *const GLvoid,
+			);
+		}
+	}
+
+	// How many fragments the voxelizers tried to append, which can be more than the capacity.
+	// Reading it back stalls
+	pub fn count(&self) -> usize {
+		let mut count = 0u32;
+		unsafe {
+			gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+			gl::GetNamedBufferSubData(
+				self.handle,
+				0,
+				size_of::<u32>() as isize,
+				&mut count as *mut u32 as *mut GLvoid,
+			);
+		}
+
+		count as usize
+	}
+
+	// Copies the first `count` stored fragments back to the CPU, stalling like `count`
+	pub fn read(&self, count: usize) -> Vec<VoxelFragment> {
+		let count = count.min(self.capacity);
+		let mut packed = vec![0u32; count * 4];
+		unsafe {
+			gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+			gl::GetNamedBufferSubData(
+				self.handle,
+				HEADER_SIZE as isize,
+				(count * FRAGMENT_SIZE) as isize,
+				packed.as_mut_ptr() as *mut GLvoid,
+			);
+		}
+
+		packed.chunks(4).map(VoxelFragment::unpack).collect()
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, count: usize, path: P) -> Result<(), csv::Error> {
+		let mut writer = csv::Writer::from_path(path)?;
+		for fragment in self.read(count) {
+			writer.serialize(fragment)?;
+		}
+		writer.flush()?;
+
+		Ok(())
+	}
+
+	// Grows the list to hold at least `count` fragments, dropping its contents
+	pub fn reserve(&mut self, count: usize) {
+		if count <= self.capacity {
+			return;
+		}
+
+		let capacity = count.next_power_of_two();
+		unsafe {
+			gl::DeleteBuffers(1, &self.handle);
+		}
+		self.handle = allocate_buffer(capacity);
+		self.capacity = capacity;
+	}
+}
+
+impl Drop for FragmentList {
+	fn drop(&mut self) {
+		unsafe {
+			gl::DeleteBuffers(1, &self.handle);
+		}
+	}
+}
+
+fn allocate_buffer(capacity: usize) -> u32 {
+	let mut handle = 0;
+	unsafe {
+		gl::CreateBuffers(1, &mut handle);
+		gl::NamedBufferData(
+			handle,
+			(HEADER_SIZE + capacity * FRAGMENT_SIZE) as isize,
+			ptr::null(),
+			gl::DYNAMIC_COPY,
+		);
+	}
+
+	handle
+}
+
+// Same as unpackUnorm4x8, the first component in the lowest byte
+fn unpack_unorm(packed: u32) -> [f32; 4] {
+	let mut components = [0.0; 4];
+	for (i, component) in components.iter_mut().enumerate() {
+		*component = ((packed >> (8 * i)) & 0xFF) as f32 / 255.0;
+	}
+
+	components
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unpack_unorm_starts_at_the_lowest_byte() {
+		assert_eq!(unpack_unorm(0xFF33_0000), [0.0, 0.0, 0.2, 1.0]);
+		assert_eq!(unpack_unorm(0x0000_00FF), [1.0, 0.0, 0.0, 0.0]);
+	}
+
+	#[test]
+	fn fragments_unpack_their_coordinates_and_attributes() {
+		let x = 1023;
+		let y = 512;
+		let z = 7;
+		let fragment = VoxelFragment::unpack(&[
+			x | (y << 10) | (z << 20),
+			0xFF00_33FF,
+			0x0000_FF00,
+			0x0000_0033,
+		]);
+
+		assert_eq!((fragment.x, fragment.y, fragment.z), (x, y, z));
+		assert_eq!(
+			(fragment.albedo_r, fragment.albedo_g, fragment.albedo_b),
+			(1.0, 0.2, 0.0)
+		);
+		assert_eq!(
+			(fragment.normal_x, fragment.normal_y, fragment.normal_z),
+			(-1.0, 1.0, -1.0)
+		);
+		assert_eq!(
+			(
+				fragment.emission_r,
+				fragment.emission_g,
+				fragment.emission_b
+			),
+			(0.2, 0.0, 0.0)
+		);
+	}
+}
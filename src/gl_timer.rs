@@ -1,111 +1,343 @@
 use gl;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::time::Instant;
 
-type Frame = Vec<f32>;
+// Queries in flight per label, results are read back this many frames later at most
+const QUERY_RING_SIZE: usize = 4;
+const ROLLING_WINDOW: usize = 120;
 
-pub struct GlTimer {
-	query_ids: Vec<u32>,
-	frames: Vec<Frame>,
-	handles: HashMap<String, usize>,
-	frame_counter: usize,
-	max_frames: usize,
+#[derive(Clone, Copy)]
+pub struct Sample {
+	pub frame: usize,
+	pub gpu_ms: f32,
+	pub cpu_ms: f32,
 }
 
-impl GlTimer {
-	pub fn new(num_queries: usize, max_frames: usize) -> GlTimer {
-		let mut query_ids = Vec::<u32>::new();
-		query_ids.resize(num_queries, 0);
+#[derive(Clone, Copy, Default)]
+pub struct RollingStats {
+	pub min: f32,
+	pub mean: f32,
+	pub max: f32,
+}
+
+impl RollingStats {
+	fn from_values<I: Iterator<Item = f32>>(values: I) -> RollingStats {
+		let mut count = 0;
+		let mut stats = RollingStats {
+			min: std::f32::MAX,
+			mean: 0.0,
+			max: std::f32::MIN,
+		};
+
+		for value in values {
+			stats.min = stats.min.min(value);
+			stats.max = stats.max.max(value);
+			stats.mean += value;
+			count += 1;
+		}
+
+		if count == 0 {
+			return RollingStats::default();
+		}
+
+		stats.mean /= count as f32;
+		stats
+	}
+}
+
+pub struct TimerSummary<'a> {
+	pub name: &'a str,
+	pub depth: usize,
+	pub gpu: RollingStats,
+	pub cpu: RollingStats,
+}
+
+struct QuerySlot {
+	start_id: u32,
+	end_id: u32,
+	frame: usize,
+	cpu_ms: f32,
+	pending: bool,
+}
+
+struct Timer {
+	name: String,
+	depth: usize,
+	slots: Vec<QuerySlot>,
+	next_slot: usize,
+	cpu_start: Instant,
+	samples: VecDeque<Sample>,
+}
 
+impl Timer {
+	fn new(name: &str) -> Timer {
+		let mut ids = [0u32; QUERY_RING_SIZE * 2];
 		unsafe {
-			gl::GenQueries(num_queries as i32, query_ids.as_mut_ptr());
+			gl::GenQueries(ids.len() as i32, ids.as_mut_ptr());
 		}
 
-		let mut samples = Vec::new();
-		samples.resize(max_frames, 0.0);
-		let mut frames = Vec::new();
-		frames.resize(num_queries, samples);
+		let slots = ids
+			.chunks(2)
+			.map(|pair| QuerySlot {
+				start_id: pair[0],
+				end_id: pair[1],
+				frame: 0,
+				cpu_ms: 0.0,
+				pending: false,
+			})
+			.collect();
+
+		Timer {
+			name: name.to_owned(),
+			depth: 0,
+			slots,
+			next_slot: 0,
+			cpu_start: Instant::now(),
+			samples: VecDeque::new(),
+		}
+	}
+
+	// Reads back every finished query, or every pending one when `wait` is set
+	fn resolve(&mut self, wait: bool, first_frame: usize, max_frames: usize) {
+		// The slots are reused as a ring, so walk the pending ones in frame order to keep the samples oldest-first
+		let mut pending: Vec<usize> = (0..self.slots.len())
+			.filter(|&i| self.slots[i].pending)
+			.collect();
+		pending.sort_by_key(|&i| self.slots[i].frame);
+
+		for i in pending {
+			let slot = &mut self.slots[i];
+
+			if !wait {
+				let mut available = 0;
+				unsafe {
+					gl::GetQueryObjectiv(slot.end_id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+				}
+
+				// Queries complete in submission order, so the later frames aren't available either
+				if available == 0 {
+					break;
+				}
+			}
+
+			let (mut start, mut end) = (0u64, 0u64);
+			unsafe {
+				gl::GetQueryObjectui64v(slot.start_id, gl::QUERY_RESULT, &mut start);
+				gl::GetQueryObjectui64v(slot.end_id, gl::QUERY_RESULT, &mut end);
+			}
+			slot.pending = false;
+
+			// Results issued before the last reset are dropped
+			if slot.frame < first_frame {
+				continue;
+			}
+
+			if self.samples.len() == max_frames {
+				self.samples.pop_front();
+			}
+			self.samples.push_back(Sample {
+				frame: slot.frame,
+				gpu_ms: end.saturating_sub(start) as f32 * 0.000_001,
+				cpu_ms: slot.cpu_ms,
+			});
+		}
+	}
+
+	fn recent(&self) -> impl Iterator<Item = &Sample> {
+		let skip = self.samples.len().saturating_sub(ROLLING_WINDOW);
+		self.samples.iter().skip(skip)
+	}
+}
 
+pub struct GlTimer {
+	timers: Vec<Timer>,
+	handles: HashMap<String, usize>,
+	scopes: Vec<usize>,
+	frame_counter: usize,
+	first_frame: usize,
+	max_frames: usize,
+}
+
+impl GlTimer {
+	pub fn new(max_frames: usize) -> GlTimer {
 		GlTimer {
-			query_ids,
-			frames,
+			timers: Vec::new(),
 			handles: HashMap::new(),
+			scopes: Vec::new(),
 			frame_counter: 0,
+			first_frame: 0,
 			max_frames,
 		}
 	}
 
-	pub fn begin_frame(&self) {}
+	pub fn begin_frame(&mut self) {
+		for timer in self.timers.iter_mut() {
+			timer.resolve(false, self.first_frame, self.max_frames);
+		}
+	}
 
 	pub fn end_frame(&mut self) {
-		self.frame_counter = (self.frame_counter + 1) % self.max_frames;
+		if let Some(handle) = self.scopes.last() {
+			report_misuse(&format!(
+				"GlTimer scope '{}' was never closed",
+				self.timers[*handle].name
+			));
+			self.scopes.clear();
+		}
+
+		self.frame_counter += 1;
+	}
+
+	// Waits for every query in flight, so the samples cover all the frames timed so far
+	pub fn flush(&mut self) {
+		for timer in self.timers.iter_mut() {
+			timer.resolve(true, self.first_frame, self.max_frames);
+		}
 	}
 
 	pub fn begin(&mut self, name: &'static str) {
-		let id = self.fetch_query_id(name);
+		let handle = self.fetch_handle(name);
+		let (first_frame, max_frames) = (self.first_frame, self.max_frames);
+		let timer = &mut self.timers[handle];
+
+		// The GPU is a whole ring behind, wait for the oldest result instead of dropping it
+		if timer.slots[timer.next_slot].pending {
+			timer.resolve(true, first_frame, max_frames);
+		}
+
+		timer.depth = self.scopes.len();
+		timer.cpu_start = Instant::now();
 		unsafe {
-			gl::BeginQuery(gl::TIME_ELAPSED, id);
+			gl::QueryCounter(timer.slots[timer.next_slot].start_id, gl::TIMESTAMP);
 		}
+
+		self.scopes.push(handle);
 	}
 
-	pub fn end(&mut self, _name: &'static str) {
+	pub fn end(&mut self, name: &'static str) {
+		let handle = match self.scopes.pop() {
+			Some(handle) => handle,
+			None => {
+				report_misuse(&format!("GlTimer scope '{}' ended without beginning", name));
+				return;
+			}
+		};
+
+		let timer = &mut self.timers[handle];
+		if timer.name != name {
+			report_misuse(&format!(
+				"GlTimer scope '{}' ended while '{}' was open, scopes must be closed in the reverse order they were opened",
+				name, timer.name
+			));
+		}
+
+		let slot = &mut timer.slots[timer.next_slot];
 		unsafe {
-			gl::EndQuery(gl::TIME_ELAPSED);
+			gl::QueryCounter(slot.end_id, gl::TIMESTAMP);
 		}
+		slot.cpu_ms = timer.cpu_start.elapsed().as_secs_f32() * 1000.0;
+		slot.frame = self.frame_counter;
+		slot.pending = true;
+
+		timer.next_slot = (timer.next_slot + 1) % QUERY_RING_SIZE;
 	}
 
-	fn fetch_query_id(&mut self, name: &'static str) -> u32 {
-		let id = if let Some(handle) = self.get_handle(name) {
-			let handle = *handle;
+	fn fetch_handle(&mut self, name: &'static str) -> usize {
+		if let Some(handle) = self.handles.get(name) {
+			return *handle;
+		}
 
-			let gl_id = self.query_ids.get_mut(handle).unwrap();
-			let mut result = 0;
-			unsafe {
-				gl::GetQueryObjectuiv(*gl_id as u32, gl::QUERY_RESULT, &mut result);
-			}
+		let handle = self.timers.len();
+		self.timers.push(Timer::new(name));
+		self.handles.insert(name.to_owned(), handle);
 
-			let frame = self.frame_counter;
-			self.frames[handle][frame] = result as f32 * 0.000_001;
+		handle
+	}
 
-			gl_id
-		} else {
-			let new_handle = self.handles.len();
-			self.handles.insert(name.to_string(), new_handle);
+	// Discards the samples recorded so far, the queries themselves are kept
+	pub fn reset(&mut self) {
+		for timer in self.timers.iter_mut() {
+			timer.samples.clear();
+		}
 
-			self
-				.query_ids
-				.get(new_handle)
-				.expect("Not enough queries allocated.")
-		};
+		self.first_frame = self.frame_counter;
+	}
 
-		*id
+	// Labels in the order they were first timed
+	pub fn labels(&self) -> Vec<&str> {
+		self
+			.timers
+			.iter()
+			.map(|timer| timer.name.as_str())
+			.collect()
 	}
 
-	fn get_handle(&self, name: &str) -> Option<&usize> {
-		self.handles.get(name)
+	// GPU times in milliseconds recorded since the last reset, oldest first
+	pub fn samples(&self, name: &str) -> Vec<f32> {
+		match self.handles.get(name) {
+			Some(handle) => self.timers[*handle]
+				.samples
+				.iter()
+				.map(|sample| sample.gpu_ms)
+				.collect(),
+			None => Vec::new(),
+		}
 	}
 
-	pub fn save_file(&self, file_name: &str) -> Result<(), ()> {
-		use csv::Writer;
+	// The last `count` samples of a label, oldest first
+	pub fn history(&self, name: &str, count: usize) -> Vec<Sample> {
+		match self.handles.get(name) {
+			Some(handle) => {
+				let samples = &self.timers[*handle].samples;
+				let skip = samples.len().saturating_sub(count);
+				samples.iter().skip(skip).cloned().collect()
+			}
+			None => Vec::new(),
+		}
+	}
 
-		let mut writer = Writer::from_path(file_name).unwrap();
+	// Min/mean/max over the last few seconds of every label
+	pub fn summaries(&self) -> Vec<TimerSummary> {
+		self
+			.timers
+			.iter()
+			.map(|timer| TimerSummary {
+				name: &timer.name,
+				depth: timer.depth,
+				gpu: RollingStats::from_values(timer.recent().map(|sample| sample.gpu_ms)),
+				cpu: RollingStats::from_values(timer.recent().map(|sample| sample.cpu_ms)),
+			})
+			.collect()
+	}
 
-		let query_names: Vec<String> = self.handles.iter().map(|(name, _)| name.clone()).collect();
-		// writer.write_record(&query_names).unwrap();
+	pub fn save_file<P: AsRef<Path>>(&self, file_name: P) -> Result<(), csv::Error> {
+		use csv::Writer;
 
-		for name in query_names.iter() {
-			let handle = *self.get_handle(name).unwrap();
-			let mut samples = Vec::new();
+		let mut writer = Writer::from_path(file_name)?;
+		writer.write_record(&["label", "depth", "frame", "gpu_ms", "cpu_ms"])?;
 
-			for frame in 0..self.max_frames {
-				let sample = self.frames[handle][frame];
-				samples.push(sample);
+		for timer in &self.timers {
+			for sample in &timer.samples {
+				writer.write_record(&[
+					timer.name.clone(),
+					timer.depth.to_string(),
+					sample.frame.to_string(),
+					sample.gpu_ms.to_string(),
+					sample.cpu_ms.to_string(),
+				])?;
 			}
-			let record = (name, samples);
-			writer.serialize(record).unwrap();
 		}
 
-		writer.flush().unwrap();
+		writer.flush()?;
 
 		Ok(())
 	}
 }
+
+// An unbalanced scope is a bug in the caller, but only its timings are wrong. Debug builds stop on
+// it, release builds log it and carry on
+fn report_misuse(message: &str) {
+	debug_assert!(false, "{}", message);
+	println!("{}", message);
+}
@@ -1,57 +1,49 @@
 use crate::scene::model::Primitive;
+use crate::shader_layout::{
+	NORMAL_ATTRIBUTE, POSITION_ATTRIBUTE, TANGENT_ATTRIBUTE, TEX_COORD_ATTRIBUTE,
+};
 use gl_helpers::*;
-use glm::UVec3;
 use nalgebra_glm as glm;
 use std::rc::Rc;
 
 pub struct GpuPrimitive {
 	vertex_array: GLVertexArray,
-	_vertex_buffer: GLBuffer,
+	vertex_buffer: GLBuffer,
 	index_buffer: Option<GLBuffer>,
 	count_vertices: usize,
+	tex_coords_offset: usize,
+	normals_offset: usize,
+	tangents_offset: usize,
+	// Without tangents the voxelizers can't map the normals
+	has_tangents: bool,
 	material: Option<Rc<GpuMaterial>>,
+	// Bounds of the vertex positions, before the transform
+	local_min: glm::Vec3,
+	local_max: glm::Vec3,
 	position: glm::Vec3,
 	scale: glm::Vec3,
+	// Moves between frames, so it's voxelized every frame instead of once with the static geometry
+	dynamic: bool,
 }
 
 impl GpuPrimitive {
-	pub fn from_volume(resolution: UVec3, program: &GLProgram) -> GpuPrimitive {
-		let mut buffer = Vec::<f32>::new();
-
-		let (width, height, depth) = (resolution.x, resolution.y, resolution.z);
-		for i in 0..width * height * depth {
-			buffer.push(0.0);
-			buffer.push(0.0);
-			buffer.push(0.0);
-		}
-
-		let vertex_buffer = GLBuffer::new(BufferTarget::Array, 0, Usage::StaticDraw, &buffer);
-
-		let mut vertex_array = GLVertexArray::new();
-		vertex_array.bind();
-		vertex_array.add_attribute(&vertex_buffer, program.get_attribute("aPosition"), 0);
-		vertex_array.enable_attributes();
-
-		GpuPrimitive {
-			vertex_array,
-			_vertex_buffer: vertex_buffer,
-			index_buffer: None,
-			count_vertices: (width * height * depth) as usize,
-			material: None,
-			position: glm::vec3(0.0, 0.0, 0.0),
-			scale: glm::vec3(1.0, 1.0, 1.0),
-		}
-	}
-
 	pub fn new(
 		primitive: &Primitive,
-		program: &GLProgram,
 		material: Rc<GpuMaterial>,
 		position: glm::Vec3,
 		scale: glm::Vec3,
+		dynamic: bool,
 	) -> GpuPrimitive {
 		let mut buffer = Vec::<f32>::new();
 
+		let mut local_min = glm::vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+		let mut local_max = -local_min;
+		for position in &primitive.positions {
+			let position = glm::make_vec3(position);
+			local_min = glm::min2(&local_min, &position);
+			local_max = glm::max2(&local_max, &position);
+		}
+
 		for position in &primitive.positions {
 			buffer.push(position[0]);
 			buffer.push(position[1]);
@@ -84,20 +76,16 @@ impl GpuPrimitive {
 		let tex_coords_size = primitive.tex_coords.len() * 2;
 		let normals_size = primitive.normals.len() * 3;
 
-		vertex_array.add_attribute(&vertex_buffer, program.get_attribute("aPosition"), 0);
-		vertex_array.add_attribute(
-			&vertex_buffer,
-			program.get_attribute("aTexCoord"),
-			positions_size,
-		);
+		vertex_array.add_attribute(&vertex_buffer, POSITION_ATTRIBUTE as _, 0);
+		vertex_array.add_attribute(&vertex_buffer, TEX_COORD_ATTRIBUTE as _, positions_size);
 		vertex_array.add_attribute(
 			&vertex_buffer,
-			program.get_attribute("aNormal"),
+			NORMAL_ATTRIBUTE as _,
 			positions_size + tex_coords_size,
 		);
 		vertex_array.add_attribute(
 			&vertex_buffer,
-			program.get_attribute("aTangent"),
+			TANGENT_ATTRIBUTE as _,
 			positions_size + tex_coords_size + normals_size,
 		);
 
@@ -112,12 +100,19 @@ impl GpuPrimitive {
 
 		GpuPrimitive {
 			vertex_array,
-			_vertex_buffer: vertex_buffer,
+			vertex_buffer,
 			index_buffer: Some(index_buffer),
 			count_vertices: primitive.indices.len(),
+			tex_coords_offset: positions_size,
+			normals_offset: positions_size + tex_coords_size,
+			tangents_offset: positions_size + tex_coords_size + normals_size,
+			has_tangents: !primitive.tangents.is_empty(),
 			material: Some(material),
+			local_min,
+			local_max,
 			position,
 			scale,
+			dynamic,
 		}
 	}
 
@@ -130,10 +125,40 @@ impl GpuPrimitive {
 		}
 	}
 
+	// Exposes the raw vertex and index buffers to passes that fetch triangles themselves
+	pub fn bind_storage(&self, vertex_binding: u32, index_binding: u32) {
+		unsafe {
+			gl::BindBufferBase(
+				gl::SHADER_STORAGE_BUFFER,
+				vertex_binding,
+				self.vertex_buffer.id(),
+			);
+			if let Some(ibo) = &self.index_buffer {
+				gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, index_binding, ibo.id());
+			}
+		}
+	}
+
 	pub const fn count_vertices(&self) -> usize {
 		self.count_vertices
 	}
 
+	pub const fn tex_coords_offset(&self) -> usize {
+		self.tex_coords_offset
+	}
+
+	pub const fn normals_offset(&self) -> usize {
+		self.normals_offset
+	}
+
+	pub const fn tangents_offset(&self) -> usize {
+		self.tangents_offset
+	}
+
+	pub const fn has_tangents(&self) -> bool {
+		self.has_tangents
+	}
+
 	pub fn material(&self) -> Rc<GpuMaterial> {
 		Rc::clone(&self.material.as_ref().unwrap())
 	}
@@ -149,6 +174,13 @@ impl GpuPrimitive {
 		unsafe { std::mem::transmute(transmute_me) }
 	}
 
+	// The world space box around the transformed primitive, as its minimum and maximum corners
+	pub fn world_bounds(&self) -> (glm::Vec3, glm::Vec3) {
+		let a = self.local_min.component_mul(&self.scale) + self.position;
+		let b = self.local_max.component_mul(&self.scale) + self.position;
+		(glm::min2(&a, &b), glm::max2(&a, &b))
+	}
+
 	pub fn translation_mut(&mut self) -> &mut glm::Vec3 {
 		&mut self.position
 	}
@@ -156,6 +188,14 @@ impl GpuPrimitive {
 	pub fn scaling_mut(&mut self) -> &mut glm::Vec3 {
 		&mut self.scale
 	}
+
+	pub const fn is_dynamic(&self) -> bool {
+		self.dynamic
+	}
+
+	pub fn dynamic_mut(&mut self) -> &mut bool {
+		&mut self.dynamic
+	}
 }
 
 pub struct GpuMaterial {
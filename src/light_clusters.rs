@@ -0,0 +1,178 @@
+use crate::scene::camera::Camera;
+use crate::scene::light::Light;
+use crate::shader_layout::*;
+use gl;
+use gl::types::*;
+use gl_helpers::*;
+use nalgebra_glm as glm;
+use std::mem::size_of;
+use std::ptr;
+
+// Match the constants of light_clusters.glsl
+const CLUSTERS: [u32; 3] = [16, 9, 24];
+const CLUSTER_COUNT: usize = (CLUSTERS[0] * CLUSTERS[1] * CLUSTERS[2]) as usize;
+// The light count of a cluster followed by room for 127 light indices
+const CLUSTER_SIZE: usize = 128 * size_of::<u32>();
+// The first slice starts at the camera and ends here, the others grow exponentially up to the far
+// plane
+const CLUSTER_NEAR: f32 = 0.1;
+
+// No light renders a shadow map yet
+const NO_SHADOW: i32 = -1;
+
+// Matches `Light` in lights.glsl
+#[repr(C)]
+struct GpuLight {
+	position: [f32; 3],
+	kind: i32,
+	direction: [f32; 3],
+	range: f32,
+	color: [f32; 3],
+	shadow_index: i32,
+	size: [f32; 2],
+	cos_inner: f32,
+	cos_outer: f32,
+}
+
+impl GpuLight {
+	fn new(light: &Light) -> GpuLight {
+		let (cos_inner, cos_outer) = light.cone_cosines();
+		let direction = light
+			.direction
+			.try_normalize(1e-6)
+			.unwrap_or_else(|| glm::vec3(0.0, -1.0, 0.0));
+
+		GpuLight {
+			position: light.position.into(),
+			kind: light.kind as i32,
+			direction: direction.into(),
+			range: light.range,
+			color: (light.color * light.intensity).into(),
+			shadow_index: NO_SHADOW,
+			size: light.size.into(),
+			cos_inner,
+			cos_outer,
+		}
+	}
+}
+
+// The lights of lights.glsl, shared by the scene shading and the light injection. Grows with the
+// number of lights
+pub struct LightBuffer {
+	handle: u32,
+	capacity: usize,
+	count: usize,
+}
+
+impl LightBuffer {
+	pub fn new() -> LightBuffer {
+		LightBuffer {
+			handle: 0,
+			capacity: 0,
+			count: 0,
+		}
+	}
+
+	pub fn upload(&mut self, lights: &[Light]) {
+		let gpu_lights: Vec<GpuLight> = lights.iter().map(GpuLight::new).collect();
+		let bytes = (gpu_lights.len() * size_of::<GpuLight>()) as isize;
+
+		unsafe {
+			if gpu_lights.len() > self.capacity {
+				if self.handle != 0 {
+					gl::DeleteBuffers(1, &self.handle);
+				}
+				gl::CreateBuffers(1, &mut self.handle);
+				gl::NamedBufferData(
+					self.handle,
+					bytes,
+					gpu_lights.as_ptr() as *const GLvoid,
+					gl::DYNAMIC_DRAW,
+				);
+				self.capacity = gpu_lights.len();
+			} else if !gpu_lights.is_empty() {
+				gl::NamedBufferSubData(self.handle, 0, bytes, gpu_lights.as_ptr() as *const GLvoid);
+			}
+		}
+		self.count = gpu_lights.len();
+	}
+
+	// Binds the lights and sets their count, the program reading them must be bound
+	pub fn bind(&self) {
+		unsafe {
+			gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, LIGHTS_BINDING, self.handle);
+		}
+		LIGHT_COUNT.set(self.count as u32);
+	}
+}
+
+impl Drop for LightBuffer {
+	fn drop(&mut self) {
+		if self.handle != 0 {
+			unsafe {
+				gl::DeleteBuffers(1, &self.handle);
+			}
+		}
+	}
+}
+
+// The view frustum split into clusters that each list the lights reaching them, so a fragment only
+// evaluates the lights around it
+pub struct LightClusters {
+	handle: u32,
+}
+
+impl LightClusters {
+	pub fn new() -> LightClusters {
+		let mut handle = 0;
+		unsafe {
+			gl::CreateBuffers(1, &mut handle);
+			gl::NamedBufferData(
+				handle,
+				(CLUSTER_COUNT * CLUSTER_SIZE) as isize,
+				ptr::null(),
+				gl::DYNAMIC_COPY,
+			);
+		}
+
+		LightClusters { handle }
+	}
+
+	// Binds the clusters and sets how they are laid out around the camera, the program reading them
+	// must be bound
+	pub fn bind(&self, camera: &Camera) {
+		unsafe {
+			gl::BindBufferBase(
+				gl::SHADER_STORAGE_BUFFER,
+				LIGHT_CLUSTERS_BINDING,
+				self.handle,
+			);
+		}
+		CLUSTER_DEPTH.set(&[CLUSTER_NEAR, Camera::FAR]);
+		VIEW.set(&camera.view_raw());
+	}
+
+	// Lists the lights reaching each cluster
+	pub fn cull(&self, program: &GLProgram, lights: &LightBuffer, camera: &Camera) {
+		let mut inverse_projection = [0.0; 16];
+		inverse_projection.copy_from_slice(glm::inverse(&camera.projection()).as_slice());
+
+		program.bind();
+		lights.bind();
+		self.bind(camera);
+		INVERSE_PROJECTION.set(&inverse_projection);
+
+		unsafe {
+			gl::DispatchCompute(CLUSTERS[0] / 4, CLUSTERS[1] / 3, CLUSTERS[2] / 4);
+			gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+		}
+	}
+}
+
+impl Drop for LightClusters {
+	fn drop(&mut self) {
+		unsafe {
+			gl::DeleteBuffers(1, &self.handle);
+		}
+	}
+}
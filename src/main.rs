@@ -1,107 +1,67 @@
 use std::str;
 use std::time::Instant;
 
+mod accuracy;
+mod benchmark;
+mod capabilities;
+mod clipmap;
+mod fragment_list;
 mod gl_timer;
 mod gl_utils;
 mod gpu_model;
+mod light_clusters;
+mod octree;
+mod options;
 mod renderer;
 mod renderer_utils;
+mod shader_layout;
+mod shader_manager;
+mod shader_sources;
+#[cfg(test)]
+mod shader_validation;
+mod statistics;
 mod textures;
+mod timing_graphs;
+mod voxel_sums;
+mod voxel_view;
 
 use nalgebra_glm as glm;
 
 mod scene;
+use accuracy::{AccumulationComparison, AccuracyConfig, AccuracyReport};
+use benchmark::{Benchmark, Progress};
+use capabilities::Feature;
+use clipmap::MAX_CASCADES;
+use options::Options;
 use renderer::*;
 use scene::camera::*;
-use scene::model::{Mesh, Resources};
+use scene::description::load_scene;
+use scene::light::{Light, LightKind};
+use shader_sources::ShaderSources;
+use textures::{Volume, VolumeStorage, RADIANCE_MIPS};
+use timing_graphs::TimingGraphs;
+use voxel_view::SliceAxis;
+
+use structopt::StructOpt;
 
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 
 struct ImGuiState {
 	resolution_index: usize,
-}
-
-fn test_scene(renderer: &mut Renderer, camera: &mut Camera, scene_name: &mut &'static str) {
-	*scene_name = "test";
-
-	use glm::vec3;
-
-	let mut resources = Resources::new();
-	renderer.submit_mesh(&Mesh::new(
-		"assets/models/test.glb",
-		vec3(0.0, 2.0, 0.0),
-		vec3(2.0, 2.0, 2.0),
-		&mut resources,
-	));
-
-	let volume = renderer.volume_mut();
-	*volume.translation_mut() = vec3(0.0, 5.0, 0.0);
-	*volume.scaling_mut() = vec3(10.0, 10.0, 10.0);
-	*volume.view_translation_mut() = vec3(10.15, 5.0, 0.0);
-	*volume.view_scaling_mut() = vec3(10.0, 10.0, 10.0);
-
-	camera.position = vec3(5.0, 2.0, 10.0);
-	camera.yaw = -90.0;
-	camera.pitch = 0.0;
-}
-
-fn sponza_scene(renderer: &mut Renderer, camera: &mut Camera, scene_name: &mut &'static str) {
-	*scene_name = "sponza";
-
-	use glm::vec3;
-
-	let mut resources = Resources::new();
-	renderer.submit_mesh(&Mesh::new(
-		"assets/models/sponza.glb",
-		vec3(0.0, 0.0, 0.0),
-		vec3(1.0, 1.0, 1.0),
-		&mut resources,
-	));
-
-	let volume = renderer.volume_mut();
-	*volume.translation_mut() = vec3(0.0, 5.0, 0.0);
-	*volume.scaling_mut() = vec3(24.0, 10.1, 12.0);
-	*volume.view_translation_mut() = vec3(0.0, 5.0, 0.0);
-	*volume.view_scaling_mut() = vec3(24.0, 10.1, 12.0);
-
-	camera.position = vec3(4.0, 2.0, 0.0);
-	camera.yaw = 0.0;
-	camera.pitch = 0.0;
-}
-
-fn cornell_scene(renderer: &mut Renderer, camera: &mut Camera, scene_name: &mut &'static str) {
-	*scene_name = "cornell";
-
-	use glm::vec3;
-
-	let mut resources = Resources::new();
-	renderer.submit_mesh(&Mesh::new(
-		"assets/models/sphere.glb",
-		vec3(0.0, 1.4, 5.0),
-		vec3(1.0, 1.0, 1.0),
-		&mut resources,
-	));
-	renderer.submit_mesh(&Mesh::new(
-		"assets/models/cornell_box.glb",
-		vec3(0.0, 0.0, 0.0),
-		vec3(1.0, 1.0, 1.0),
-		&mut resources,
-	));
-
-	let volume = renderer.volume_mut();
-	*volume.translation_mut() = vec3(0.0, 5.0, 0.0);
-	*volume.scaling_mut() = vec3(10.0, 10.0, 10.0);
-	*volume.view_translation_mut() = vec3(10.15, 5.0, 0.0);
-	*volume.view_scaling_mut() = vec3(10.0, 10.0, 10.0);
-
-	camera.position = vec3(0.0, 5.0, 10.0);
-	camera.yaw = -90.0;
-	camera.pitch = 0.0;
+	scene_path: imgui::ImString,
+	scene_to_load: Option<String>,
+	timing_graphs: TimingGraphs,
+	accuracy_report: Option<AccuracyReport>,
+	accumulation_comparison: Option<AccumulationComparison>,
+	show_slice: bool,
+	slice_axis: SliceAxis,
+	slice_index: i32,
 }
 
 fn main() {
 	const WINDOW_TITLE: &str = "Potato Renderer 🥟";
-	let (width, height) = (1280, 720);
+	let options = Options::from_args();
+	let (width, height) = (options.width, options.height);
 
 	let logical_size = glutin::dpi::LogicalSize::from((width, height));
 	let window_builder = glutin::window::WindowBuilder::new()
@@ -121,30 +81,52 @@ fn main() {
 	let mut platform = WinitPlatform::init(&mut imgui);
 	platform.attach_window(imgui.io_mut(), &window_gl.window(), HiDpiMode::Default);
 
-	let resolutions = [64, 128, 256];
-	let res_index = 0;
-	let conservative = true;
+	let resolutions = [64, 128, 256, 512, 1024];
+	// Dense volumes past 256³ take gigabytes, only the octree offers the larger resolutions
+	const DENSE_RESOLUTIONS: usize = 3;
 
 	// Renderer setup
 	let mut renderer = renderer::Renderer::new(
 		&window_gl,
 		logical_size,
-		resolutions[res_index],
-		conservative,
-	);
+		options.resolution.unwrap_or(resolutions[0]),
+		options.storage.unwrap_or(VolumeStorage::Dense),
+		options.conservative.unwrap_or(ConservativeMode::Nv),
+		&ShaderSources::new(options.shader_dir.clone()),
+	)
+	.unwrap_or_else(|error| {
+		println!("{}", error);
+		std::process::exit(1);
+	});
 
 	let imgui_renderer =
 		imgui_opengl_renderer::Renderer::new(&mut imgui, |s| window_gl.get_proc_address(s) as _);
 
+	let mut camera = Camera::new(glm::vec3(0.0, 0.0, 0.0), 0.0, 0.0);
+	let mut scene_name = load_scene(&mut renderer, &mut camera, &options.scene)
+		.expect("Couldn't load the initial scene");
+	options.apply(&mut renderer);
+
+	if let Some(path) = &options.accuracy {
+		let config = AccuracyConfig::load(path).expect("Couldn't load the accuracy sweep");
+		if let Err(error) = accuracy::run_sweep(&mut renderer, &mut camera, &config) {
+			println!("{}", error);
+		}
+		return;
+	}
+
 	let mut imgui_state = ImGuiState {
-		resolution_index: res_index,
+		resolution_index: resolution_index(&resolutions, &renderer).unwrap_or(0),
+		scene_path: imgui::ImString::new(options.scene.clone()),
+		scene_to_load: None,
+		timing_graphs: TimingGraphs::new(),
+		accuracy_report: None,
+		accumulation_comparison: None,
+		show_slice: false,
+		slice_axis: SliceAxis::Z,
+		slice_index: 0,
 	};
-
-	let mut camera = Camera::new(glm::vec3(0.0, 0.0, 0.0), 0.0, 0.0);
-	let mut scene_name = "INVALID";
-	// cornell_scene(&mut renderer, &mut camera, &mut scene_name);
-	sponza_scene(&mut renderer, &mut camera, &mut scene_name);
-	// test_scene(&mut renderer, &mut camera, &mut scene_name);
+	imgui_state.scene_path.reserve(256);
 
 	let mut key_states = KeyStates::new();
 
@@ -156,6 +138,11 @@ fn main() {
 	dt /= MAX_DELTAS as f32;
 	let initial_time = Instant::now();
 	let mut start_frame_time = Instant::now();
+	let mut frame_count: usize = 0;
+
+	let mut benchmark = options.benchmark.as_ref().map(|path| {
+		Benchmark::load(path, renderer.capabilities()).expect("Couldn't load the benchmark")
+	});
 
 	event_loop.run(move |event, _, control_flow| {
 		*control_flow = ControlFlow::Wait;
@@ -198,24 +185,81 @@ fn main() {
 						(K, _) => key_states.K = state,
 						(I, _) => key_states.I = state,
 
-						(P, Released) => renderer.save_diagnostics(scene_name),
+						(P, Released) => renderer.save_diagnostics(&scene_name),
 
 						_ => (),
 					}
 				}
 				WindowEvent::RedrawRequested => {
+					if let Some(benchmark) = &mut benchmark {
+						match benchmark.step(&mut renderer, &mut camera, &mut scene_name) {
+							Ok(Progress::Running) => (),
+							Ok(Progress::Finished) => {
+								if let Err(error) = benchmark.save(renderer.capabilities()) {
+									println!("{}", error);
+								}
+								*control_flow = ControlFlow::Exit;
+							}
+							Err(error) => {
+								println!("{}", error);
+								*control_flow = ControlFlow::Exit;
+							}
+						}
+					}
+
 					imgui.io_mut().update_delta_time(start_frame_time);
 
+					imgui_state.timing_graphs.update(renderer.timer());
+
 					let ui = imgui.frame();
 					{
 						use imgui::*;
 						use std::borrow::Cow;
 
 						Window::new(im_str!("Diagnostics"))
-							.size([300.0, 100.0], Condition::FirstUseEver)
+							.size([400.0, 500.0], Condition::FirstUseEver)
 							.build(&ui, || {
+								ui.text(format!("GPU: {}", renderer.capabilities().renderer));
 								ui.text(format!("Frame rate: {:.2} frames/s", 1.0 / dt));
 								ui.text(format!("Frame time: {:.2} ms", dt * 1000.0));
+								if let Some(benchmark) = &benchmark {
+									ui.text(format!("Benchmark {}", benchmark.status()));
+								}
+								ui.separator();
+
+								ui.text("GPU ms (min / mean / max), CPU ms (mean)");
+								for summary in renderer.timer().summaries() {
+									ui.text(format!(
+										"{:indent$}{}: {:.3} / {:.3} / {:.3}, {:.3}",
+										"",
+										summary.name,
+										summary.gpu.min,
+										summary.gpu.mean,
+										summary.gpu.max,
+										summary.cpu.mean,
+										indent = summary.depth * 2
+									));
+								}
+								ui.separator();
+
+								if ui
+									.collapsing_header(im_str!("Pass timings"))
+									.default_open(true)
+									.build()
+								{
+									imgui_state.timing_graphs.draw(&ui);
+								}
+
+								if ui
+									.collapsing_header(im_str!("Voxelization statistics"))
+									.build()
+								{
+									ui.checkbox(
+										im_str!("Collect every frame (stalls)"),
+										&mut renderer.collect_statistics,
+									);
+									draw_statistics(&ui, renderer.statistics());
+								}
 								ui.separator();
 
 								let mouse_pos = ui.io().mouse_pos;
@@ -225,12 +269,89 @@ fn main() {
 								));
 							});
 
+						let shader_errors = renderer.shader_errors();
+						if !shader_errors.is_empty() {
+							Window::new(im_str!("Shader errors"))
+								.size([500.0, 300.0], Condition::FirstUseEver)
+								.build(&ui, || {
+									ui.text("The previous version of these programs is still in use.");
+									for (name, log) in &shader_errors {
+										ui.separator();
+										ui.text_colored([1.0, 0.4, 0.4, 1.0], &im_str!("{}", name));
+										ui.text_wrapped(&im_str!("{}", log));
+									}
+								});
+						}
+
+						Window::new(im_str!("Scene")).build(&ui, || {
+							ui.text(format!("Current scene: {}", scene_name));
+							ui.input_text(im_str!("Path"), &mut imgui_state.scene_path)
+								.build();
+							if ui.button(im_str!("Load scene"), [100.0, 20.0]) {
+								imgui_state.scene_to_load = Some(imgui_state.scene_path.to_str().to_owned());
+							}
+						});
+
 						Window::new(im_str!("Lights")).build(&ui, || {
 							let lights = &mut renderer.lights;
+							ui.text(format!("{} lights", lights.len()));
+							if ui.button(im_str!("Add light"), [100.0, 20.0]) {
+								lights.push(Light::point(
+									glm::vec3(0.0, 2.0, 0.0),
+									glm::vec3(1.0, 1.0, 1.0),
+									1.0,
+								));
+							}
+							ui.same_line(0.0);
+							if ui.button(im_str!("Remove light"), [100.0, 20.0]) {
+								lights.pop();
+							}
 							for (i, light) in lights.iter_mut().enumerate() {
+								ui.separator();
+								for (k, kind) in LightKind::ALL.iter().enumerate() {
+									if k > 0 {
+										ui.same_line(0.0);
+									}
+									ui.radio_button(&im_str!("{}##{}", kind.name(), i), &mut light.kind, *kind);
+								}
 								ColorEdit::new(&im_str!("Color#{}", i), light.color.as_mut()).build(&ui);
-								ui.drag_float3(&im_str!("Position#{}", i), light.position.as_mut())
+								ui.drag_float(&im_str!("Intensity#{}", i), &mut light.intensity)
+									.min(0.0)
+									.speed(0.01)
 									.build();
+								if light.kind != LightKind::Directional {
+									ui.drag_float3(&im_str!("Position#{}", i), light.position.as_mut())
+										.build();
+									ui.drag_float(&im_str!("Range#{}", i), &mut light.range)
+										.min(0.1)
+										.build();
+								}
+								if light.kind != LightKind::Point && light.kind != LightKind::Sphere {
+									ui.drag_float3(&im_str!("Direction#{}", i), light.direction.as_mut())
+										.speed(0.01)
+										.build();
+								}
+								match light.kind {
+									LightKind::Spot => {
+										Slider::new(&im_str!("Inner angle#{}", i), 0.0..=89.0)
+											.build(&ui, &mut light.inner_angle);
+										Slider::new(&im_str!("Outer angle#{}", i), 0.0..=90.0)
+											.build(&ui, &mut light.outer_angle);
+									}
+									LightKind::Rectangle => {
+										ui.drag_float2(&im_str!("Size#{}", i), light.size.as_mut())
+											.min(0.01)
+											.speed(0.01)
+											.build();
+									}
+									LightKind::Sphere => {
+										ui.drag_float(&im_str!("Radius#{}", i), &mut light.size.x)
+											.min(0.01)
+											.speed(0.01)
+											.build();
+									}
+									LightKind::Point | LightKind::Directional => {}
+								}
 							}
 						});
 
@@ -251,10 +372,72 @@ fn main() {
 								.build();
 
 							let index = &mut imgui_state.resolution_index;
-							ComboBox::new(im_str!("Resolution")).build_simple(&ui, index, &resolutions, &|x| {
-								Cow::from(im_str!("{}x{}x{}", x, x, x))
-							});
-							*volume.resolution_mut() = resolutions[*index];
+							let shown = match renderer.volume().storage() {
+								VolumeStorage::Dense | VolumeStorage::Clipmap => DENSE_RESOLUTIONS.max(*index + 1),
+								VolumeStorage::Octree => resolutions.len(),
+							};
+							let resolution_changed = ComboBox::new(im_str!("Resolution")).build_simple(
+								&ui,
+								index,
+								&resolutions[..shown],
+								&|x| Cow::from(im_str!("{}x{}x{}", x, x, x)),
+							);
+							if resolution_changed {
+								renderer.set_resolution(resolutions[*index]);
+							}
+
+							ui.text("Storage:");
+							let mut storage = renderer.volume().storage();
+							ui.same_line(0.0);
+							ui.radio_button(im_str!("Dense"), &mut storage, VolumeStorage::Dense);
+							if renderer.capabilities().supports(Feature::SparseOctree) {
+								ui.same_line(0.0);
+								ui.radio_button(im_str!("Octree"), &mut storage, VolumeStorage::Octree);
+							}
+							ui.same_line(0.0);
+							ui.radio_button(im_str!("Clipmap"), &mut storage, VolumeStorage::Clipmap);
+							if storage != renderer.volume().storage() {
+								if storage != VolumeStorage::Octree && *index >= DENSE_RESOLUTIONS {
+									*index = DENSE_RESOLUTIONS - 1;
+								}
+								renderer.set_volume(storage, resolutions[*index]);
+							}
+
+							let resolution = renderer.volume().resolution()[0] as usize;
+							let megabytes = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+							if let Some(octree) = renderer.volume().octree() {
+								ui.text(format!(
+									"{} fragments, {} nodes",
+									octree.fragment_count(),
+									octree.node_count()
+								));
+								ui.text(format!(
+									"Memory: {:.1} MB, {:.1} MB if dense",
+									megabytes(octree.memory_bytes()),
+									megabytes(Volume::dense_memory_bytes(resolution))
+								));
+							} else {
+								ui.text(format!(
+									"Memory: {:.1} MB",
+									megabytes(renderer.volume().memory_bytes())
+								));
+								let sums_bytes = renderer.voxel_sums_bytes();
+								if sums_bytes > 0 {
+									ui.text(format!("Voxel sums: {:.1} MB", megabytes(sums_bytes)));
+								}
+							}
+							if renderer.volume().storage() == VolumeStorage::Clipmap {
+								let mut cascades = renderer.volume().cascade_count() as i32;
+								if Slider::new(im_str!("Cascades"), 1..=MAX_CASCADES as i32)
+									.build(&ui, &mut cascades)
+								{
+									renderer.set_cascade_count(cascades as usize);
+								}
+								ui.checkbox(
+									im_str!("Incremental updates"),
+									&mut renderer.clipmap_incremental,
+								);
+							}
 							ui.separator();
 
 							ui.radio_button(
@@ -288,19 +471,73 @@ fn main() {
 								&mut renderer.rendering_mode,
 								RenderingMode::Scene,
 							);
+							// Only the radiance has mips, the other views stay on the first one
+							let mut mip = renderer.voxel_view_mip as i32;
+							if Slider::new(im_str!("Mip"), 0..=RADIANCE_MIPS as i32 - 1).build(&ui, &mut mip) {
+								renderer.voxel_view_mip = mip as usize;
+							}
 
 							ui.separator();
-							ui.radio_button(
-								im_str!("Fragment"),
-								&mut renderer.voxelization_mode,
-								VoxelizationMode::FragmentOnly,
-							);
-							ui.same_line(100.0);
-							ui.radio_button(
-								im_str!("Hybrid"),
-								&mut renderer.voxelization_mode,
-								VoxelizationMode::Hybrid,
+							let voxelizers = [
+								(im_str!("Fragment"), VoxelizationMode::FragmentOnly),
+								(im_str!("Hybrid"), VoxelizationMode::Hybrid),
+								(im_str!("Compute"), VoxelizationMode::Compute),
+							];
+							for (i, (label, mode)) in voxelizers.iter().enumerate() {
+								if !renderer.capabilities().supports(mode.feature()) {
+									continue;
+								}
+								if i > 0 {
+									ui.same_line(100.0 * i as f32);
+								}
+								ui.radio_button(label, &mut renderer.voxelization_mode, *mode);
+							}
+							// The octree is always built from the fragment list
+							if renderer.volume().storage() == VolumeStorage::Dense {
+								ui.checkbox(
+									im_str!("Fragment list output"),
+									&mut renderer.fragment_output,
+								);
+								if !renderer.fragment_output {
+									ui.checkbox(
+										im_str!("Voxelize static primitives once"),
+										&mut renderer.static_split,
+									);
+									ui.text("Accumulation:");
+									ui.same_line(0.0);
+									ui.radio_button(
+										im_str!("Packed"),
+										&mut renderer.accumulation,
+										AccumulationMode::Packed,
+									);
+									ui.same_line(0.0);
+									ui.radio_button(
+										im_str!("Sum"),
+										&mut renderer.accumulation,
+										AccumulationMode::Sum,
+									);
+								}
+							}
+							ui.checkbox(im_str!("Normal mapping"), &mut renderer.normal_mapping);
+							ui.checkbox(
+								im_str!("Revoxelize every frame"),
+								&mut renderer.force_revoxelize,
 							);
+							if let Some(count) = renderer.fragment_count() {
+								ui.text(format!("Fragment list: {} fragments", count));
+								if ui.button(im_str!("Save fragments"), [140.0, 20.0]) {
+									let file_name = format!(
+										"fragments_{}_{}_{}.csv",
+										resolution,
+										scene_name,
+										renderer.effective_voxelization_mode().name()
+									);
+									match renderer.save_fragments(&file_name) {
+										Ok(()) => println!("Fragments written to '{}'", file_name),
+										Err(e) => println!("Couldn't save the fragments: {}", e),
+									}
+								}
+							}
 							ui.separator();
 
 							Slider::new(im_str!("Cutoff"), 0.1..=10.0)
@@ -311,11 +548,66 @@ fn main() {
 								.min(0.1)
 								.max(18000.0)
 								.build();
-							ui.checkbox(
-								im_str!("GL_NV_conversative_raster"),
-								&mut renderer.nv_conservative,
-							);
+							ui.text("Conservative rasterization:");
+							let mut conservative_mode = renderer.conservative_mode();
+							for (i, mode) in renderer.supported_conservative_modes().iter().enumerate() {
+								if i > 0 {
+									ui.same_line(0.0);
+								}
+								ui.radio_button(&im_str!("{}", mode.name()), &mut conservative_mode, *mode);
+							}
+							if conservative_mode != renderer.conservative_mode() {
+								renderer.set_conservative_mode(conservative_mode);
+							}
 							ui.checkbox(im_str!("Show bounds"), &mut renderer.show_bounds);
+							ui.separator();
+
+							// The comparison reads the dense textures back
+							if renderer.volume().storage() == VolumeStorage::Dense
+								&& ui.button(im_str!("Measure accuracy"), [140.0, 20.0])
+							{
+								imgui_state.accuracy_report = Some(accuracy::measure_current(&mut renderer, 2));
+							}
+							if let Some(report) = &imgui_state.accuracy_report {
+								ui.text(format!(
+									"Against the {} voxelizer at 2x, not ground truth",
+									accuracy::REFERENCE_MODE.name()
+								));
+								ui.text(format!("IoU: {:.4}", report.iou));
+								ui.text(format!(
+									"False positives: {}, false negatives: {}",
+									report.false_positives, report.false_negatives
+								));
+								ui.text(format!(
+									"Albedo error: {:.4} {:.4} {:.4}",
+									report.albedo_error[0], report.albedo_error[1], report.albedo_error[2]
+								));
+								ui.text(format!(
+									"Normal error: {:.4} {:.4} {:.4}",
+									report.normal_error[0], report.normal_error[1], report.normal_error[2]
+								));
+							}
+
+							if renderer.volume().storage() == VolumeStorage::Dense
+								&& !renderer.fragment_output
+								&& ui.button(im_str!("Compare accumulation"), [140.0, 20.0])
+							{
+								imgui_state.accumulation_comparison =
+									Some(accuracy::compare_accumulation(&mut renderer));
+							}
+							if let Some(comparison) = &imgui_state.accumulation_comparison {
+								let report = &comparison.report;
+								ui.text("Packed averages against the sums:");
+								ui.text(format!(
+									"Albedo error: {:.4} {:.4} {:.4}",
+									report.albedo_error[0], report.albedo_error[1], report.albedo_error[2]
+								));
+								ui.text(format!(
+									"Normal error: {:.4} {:.4} {:.4}",
+									report.normal_error[0], report.normal_error[1], report.normal_error[2]
+								));
+								ui.text(format!("Saturated voxels: {}", comparison.saturated_voxels));
+							}
 						});
 
 						Window::new(im_str!("Transforms")).build(&ui, || {
@@ -331,26 +623,102 @@ fn main() {
 									.min(-100.0)
 									.max(100.0)
 									.build();
+								ui.checkbox(&im_str!("Dynamic##{}", i), primitive.dynamic_mut());
 							}
 						});
+
+						// The slices and the picker read the dense textures
+						if renderer.volume().storage() == VolumeStorage::Dense {
+							Window::new(im_str!("Voxel inspector")).build(&ui, || {
+								Slider::new(im_str!("Alpha threshold"), 0.0..=1.0)
+									.display_format(im_str!("%.2f"))
+									.build(&ui, &mut renderer.voxel_alpha_threshold);
+								ui.checkbox(im_str!("Slice"), &mut imgui_state.show_slice);
+								if !imgui_state.show_slice {
+									return;
+								}
+
+								for (i, axis) in SliceAxis::ALL.iter().enumerate() {
+									if i > 0 {
+										ui.same_line(0.0);
+									}
+									ui.radio_button(
+										&im_str!("{}", axis.name()),
+										&mut imgui_state.slice_axis,
+										*axis,
+									);
+								}
+								let size = renderer.viewed_texture().size() as i32;
+								imgui_state.slice_index = imgui_state.slice_index.min(size - 1);
+								Slider::new(im_str!("Index"), 0..=size - 1)
+									.build(&ui, &mut imgui_state.slice_index);
+
+								let axis = imgui_state.slice_axis;
+								let index = imgui_state.slice_index as usize;
+								let (texture, size) = renderer.voxel_slice(axis, index);
+								const SLICE_SIZE: f32 = 256.0;
+								let origin = ui.cursor_screen_pos();
+								// Flipped so that the second axis of the slice points up
+								Image::new(TextureId::from(texture as usize), [SLICE_SIZE, SLICE_SIZE])
+									.uv0([0.0, 1.0])
+									.uv1([1.0, 0.0])
+									.build(&ui);
+								if ui.is_item_hovered() {
+									let mouse_pos = ui.io().mouse_pos;
+									let scale = size as f32 / SLICE_SIZE;
+									let x = ((mouse_pos[0] - origin[0]) * scale) as usize;
+									let y = ((SLICE_SIZE - (mouse_pos[1] - origin[1])) * scale) as usize;
+									let pixel = [x.min(size - 1), y.min(size - 1)];
+									let voxel = axis.voxel(index, pixel);
+									let value = renderer.pick_voxel(voxel);
+									ui.text(format!("Voxel {} {} {}", voxel[0], voxel[1], voxel[2]));
+									ui.text(format!(
+										"RGBA8: {} {} {} {}",
+										value[0], value[1], value[2], value[3]
+									));
+								}
+							});
+						}
 					}
 
 					renderer.render(&camera);
 
 					imgui_renderer.render(ui);
 					window_gl.swap_buffers().unwrap();
+
+					frame_count += 1;
+					if options.frames.map_or(false, |frames| frame_count >= frames) {
+						*control_flow = ControlFlow::Exit;
+					}
+
+					if let Some(path) = imgui_state.scene_to_load.take() {
+						match load_scene(&mut renderer, &mut camera, &path) {
+							Ok(name) => {
+								scene_name = name;
+								if let Some(index) = resolution_index(&resolutions, &renderer) {
+									imgui_state.resolution_index = index;
+								}
+							}
+							Err(error) => println!("{}", error),
+						}
+					}
 				}
 				_ => (),
 			},
 			Event::EventsCleared => {
 				update_camera(&mut camera, dt as f32, &key_states);
-				let primitives = renderer.primitives_mut();
-
-				primitives[0].translation_mut().as_mut()[0] =
-					initial_time.elapsed().as_secs_f32().cos() * 3.5;
-
-				primitives[0].translation_mut().as_mut()[2] =
-					initial_time.elapsed().as_secs_f32().sin() * 3.5;
+				// Only dynamic primitives may move, the static ones are voxelized once
+				let dynamic = renderer
+					.primitives_mut()
+					.iter_mut()
+					.find(|primitive| primitive.is_dynamic());
+				if let Some(primitive) = dynamic {
+					primitive.translation_mut().as_mut()[0] =
+						initial_time.elapsed().as_secs_f32().cos() * 3.5;
+
+					primitive.translation_mut().as_mut()[2] =
+						initial_time.elapsed().as_secs_f32().sin() * 3.5;
+				}
 
 				delta_times[curr_frame] = Instant::now()
 					.duration_since(start_frame_time)
@@ -371,11 +739,70 @@ fn main() {
 			Event::DeviceEvent { event, .. } => match event {
 				_ => (),
 			},
+			Event::LoopDestroyed => {
+				if let Some(path) = &options.output_csv {
+					if let Err(e) = renderer.save_timings(path) {
+						println!("Couldn't save the timings: {}", e);
+					}
+				}
+			}
 			_ => *control_flow = ControlFlow::Poll,
 		}
 	});
 }
 
+fn draw_statistics(ui: &imgui::Ui, statistics: Option<&statistics::VoxelizationStatistics>) {
+	let statistics = match statistics {
+		Some(statistics) => statistics,
+		None => {
+			ui.text("Nothing collected yet");
+			return;
+		}
+	};
+
+	ui.text(format!("Large triangles: {}", statistics.large_triangles));
+	ui.text(format!("Small triangles: {}", statistics.small_triangles));
+	ui.text(format!("Fragments: {}", statistics.fragments));
+	ui.text(format!("Occupied voxels: {}", statistics.occupied_voxels));
+
+	if let Some(pipeline) = &statistics.pipeline {
+		ui.separator();
+		ui.text(format!(
+			"Vertices submitted: {}",
+			pipeline.vertices_submitted
+		));
+		ui.text(format!(
+			"Primitives submitted: {}",
+			pipeline.primitives_submitted
+		));
+		ui.text(format!(
+			"GS invocations: {}",
+			pipeline.geometry_shader_invocations
+		));
+		ui.text(format!(
+			"GS primitives emitted: {}",
+			pipeline.geometry_shader_primitives
+		));
+		ui.text(format!(
+			"Clipped primitives out: {}",
+			pipeline.clipping_output_primitives
+		));
+		ui.text(format!(
+			"FS invocations: {}",
+			pipeline.fragment_shader_invocations
+		));
+		ui.text(format!(
+			"CS invocations: {}",
+			pipeline.compute_shader_invocations
+		));
+	}
+}
+
+fn resolution_index(resolutions: &[usize], renderer: &Renderer) -> Option<usize> {
+	let resolution = renderer.volume().resolution()[0] as usize;
+	resolutions.iter().position(|&r| r == resolution)
+}
+
 #[derive(Debug)]
 #[allow(non_snake_case)]
 struct KeyStates {
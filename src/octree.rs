@@ -0,0 +1,353 @@
+use crate::fragment_list::FragmentList;
+use crate::renderer_utils::*;
+use crate::shader_layout::*;
+use crate::shader_sources::ShaderSources;
+use gl;
+use gl::types::*;
+use gl_helpers::*;
+use std::mem::size_of;
+use std::ptr;
+
+// Children are allocated in tiles of 8 nodes, the root is the only node outside of a tile
+const TILE_SIZE: usize = 8;
+const WORK_GROUP_SIZE: usize = 64;
+const MAX_WORK_GROUPS: usize = 65535;
+// The fragment positions are packed with 10 bits per axis
+pub const MAX_OCTREE_RESOLUTION: usize = 1024;
+
+pub struct OctreePrograms {
+	pub flag: GLProgram,
+	pub allocate: GLProgram,
+	pub store: GLProgram,
+	pub inject: GLProgram,
+	pub filter: GLProgram,
+}
+
+impl OctreePrograms {
+	pub fn load(sources: &ShaderSources) -> OctreePrograms {
+		OctreePrograms {
+			flag: load_program(sources, &OCTREE_FLAG_PROGRAM),
+			allocate: load_program(sources, &OCTREE_ALLOCATE_PROGRAM),
+			store: load_program(sources, &OCTREE_STORE_PROGRAM),
+			inject: load_program(sources, &OCTREE_INJECT_PROGRAM),
+			filter: load_program(sources, &OCTREE_FILTER_PROGRAM),
+		}
+	}
+}
+
+// Sparse voxel octree built from the voxel fragment list, after Crassin & Green. Every node has a
+// 2x2x2 brick in the brick pools holding its octants, so the deepest level has the resolution of
+// the volume and each level above it is one mip coarser
+pub struct SparseOctree {
+	resolution: usize,
+	// Levels of nodes, the root being level 0
+	depth: usize,
+	// The tile count followed by the first child of every node
+	node_pool: u32,
+	node_capacity: usize,
+	// Bricks per axis of the brick pools
+	brick_pool_width: usize,
+	albedo_bricks: u32,
+	radiance_bricks: u32,
+	// First node and number of nodes of every level, as of the last build
+	levels: Vec<(usize, usize)>,
+	fragment_count: usize,
+}
+
+impl SparseOctree {
+	pub fn new(resolution: usize) -> SparseOctree {
+		assert!(
+			resolution.is_power_of_two() && resolution >= 2 && resolution <= MAX_OCTREE_RESOLUTION,
+			"The octree needs a power of two resolution between 2 and {}, got {}",
+			MAX_OCTREE_RESOLUTION,
+			resolution
+		);
+
+		let mut octree = SparseOctree {
+			resolution,
+			depth: resolution.trailing_zeros() as usize,
+			node_pool: 0,
+			node_capacity: 0,
+			brick_pool_width: 0,
+			albedo_bricks: 0,
+			radiance_bricks: 0,
+			levels: Vec::new(),
+			fragment_count: 0,
+		};
+		// Surfaces touch about resolution² voxels, the pools grow if a scene needs more
+		octree.allocate(resolution * resolution);
+
+		octree
+	}
+
+	fn allocate(&mut self, node_capacity: usize) {
+		self.release();
+
+		self.brick_pool_width = (node_capacity as f64).cbrt().ceil() as usize;
+		self.node_capacity = self.brick_pool_width.pow(3);
+
+		unsafe {
+			gl::CreateBuffers(1, &mut self.node_pool);
+			gl::NamedBufferData(
+				self.node_pool,
+				((1 + self.node_capacity) * size_of::<u32>()) as isize,
+				ptr::null(),
+				gl::DYNAMIC_COPY,
+			);
+		}
+
+		self.albedo_bricks = allocate_brick_pool(2 * self.brick_pool_width);
+		self.radiance_bricks = allocate_brick_pool(2 * self.brick_pool_width);
+	}
+
+	fn release(&mut self) {
+		if self.node_pool == 0 {
+			return;
+		}
+
+		unsafe {
+			gl::DeleteBuffers(1, &self.node_pool);
+			let textures = [self.albedo_bricks, self.radiance_bricks];
+			gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+		}
+		self.node_pool = 0;
+	}
+
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+
+	pub fn brick_pool_width(&self) -> usize {
+		self.brick_pool_width
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.levels.last().map_or(0, |(first, count)| first + count)
+	}
+
+	pub fn fragment_count(&self) -> usize {
+		self.fragment_count
+	}
+
+	pub fn memory_bytes(&self) -> usize {
+		let brick_pool_texels = (2 * self.brick_pool_width).pow(3);
+		(1 + self.node_capacity) * size_of::<u32>() + 2 * brick_pool_texels * 4
+	}
+
+	// Subdivides the octree where the fragments are and averages their albedo into the deepest
+	// bricks. The pools are reallocated and the build restarted when they are too small
+	pub fn build(
+		&mut self,
+		programs: &OctreePrograms,
+		fragments: &FragmentList,
+		fragment_count: usize,
+	) {
+		self.fragment_count = fragment_count;
+
+		while let Err(required) = self.subdivide(programs, fragments) {
+			println!(
+				"The octree needs more than {} nodes, growing the pools",
+				self.node_capacity
+			);
+			self.allocate(required);
+		}
+
+		unsafe {
+			for bricks in &[self.albedo_bricks, self.radiance_bricks] {
+				gl::ClearTexImage(*bricks, 0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+			}
+		}
+
+		programs.store.bind();
+		RESOLUTION.set(&self.resolution_3d());
+		OCTREE_LEVEL.set(self.depth as i32 - 1);
+		BRICK_POOL_WIDTH.set(self.brick_pool_width as i32);
+		self.bind_brick_image(self.albedo_bricks, ALBEDO_BRICKS_UNIT, gl::R32UI);
+		dispatch(self.fragment_count);
+
+		unsafe {
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+		}
+	}
+
+	// Allocates the children of every level from the root down, reading the tile count back after
+	// each one to size the next dispatch. Fails with the number of nodes to allocate next time
+	fn subdivide(
+		&mut self,
+		programs: &OctreePrograms,
+		fragments: &FragmentList,
+	) -> Result<(), usize> {
+		unsafe {
+			gl::ClearNamedBufferData(
+				self.node_pool,
+				gl::R32UI,
+				gl::RED_INTEGER,
+				gl::UNSIGNED_INT,
+				ptr::null(),
+			);
+		}
+
+		fragments.bind_unit(FRAGMENT_LIST_BINDING);
+		self.bind_nodes();
+
+		self.levels = vec![(0, 1)];
+		let mut tiles = 0;
+		for level in 0..self.depth - 1 {
+			let (first, count) = self.levels[level];
+
+			programs.flag.bind();
+			RESOLUTION.set(&self.resolution_3d());
+			OCTREE_LEVEL.set(level as i32);
+			dispatch(self.fragment_count);
+
+			unsafe {
+				gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+			}
+
+			programs.allocate.bind();
+			NODE_OFFSET.set(first as u32);
+			NODE_COUNT.set(count as u32);
+			NODE_CAPACITY.set(self.node_capacity as u32);
+			dispatch(count);
+
+			let previous_tiles = tiles;
+			tiles = self.read_tile_count();
+
+			let required = 1 + tiles * TILE_SIZE;
+			if required > self.node_capacity {
+				// The deeper levels are still unknown, so leave them some room
+				return Err(required * 2);
+			}
+
+			self.levels.push((
+				1 + previous_tiles * TILE_SIZE,
+				(tiles - previous_tiles) * TILE_SIZE,
+			));
+		}
+
+		Ok(())
+	}
+
+	fn read_tile_count(&self) -> usize {
+		let mut tiles = 0u32;
+		unsafe {
+			gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT | gl::SHADER_STORAGE_BARRIER_BIT);
+			gl::GetNamedBufferSubData(
+				self.node_pool,
+				0,
+				size_of::<u32>() as isize,
+				&mut tiles as *mut u32 as *mut GLvoid,
+			);
+		}
+
+		tiles as usize
+	}
+
+	// Lights every fragment into the deepest radiance bricks. Expects the injection program to be
+	// bound with its light and volume uniforms set
+	pub fn inject(&self, fragments: &FragmentList) {
+		fragments.bind_unit(FRAGMENT_LIST_BINDING);
+		self.bind_nodes();
+		self.bind_brick_image(self.radiance_bricks, RADIANCE_BRICKS_UNIT, gl::R32UI);
+
+		RESOLUTION.set(&self.resolution_3d());
+		OCTREE_LEVEL.set(self.depth as i32 - 1);
+		BRICK_POOL_WIDTH.set(self.brick_pool_width as i32);
+		dispatch(self.fragment_count);
+
+		unsafe {
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+		}
+	}
+
+	// Resolves the coverage of the deepest bricks, then fills the bricks of every level above it
+	// from their children, bottom up
+	pub fn filter(&self, program: &GLProgram) {
+		program.bind();
+		self.bind_nodes();
+		self.bind_brick_image(self.albedo_bricks, ALBEDO_BRICKS_UNIT, gl::RGBA8);
+		self.bind_brick_image(self.radiance_bricks, RADIANCE_BRICKS_UNIT, gl::RGBA8);
+		BRICK_POOL_WIDTH.set(self.brick_pool_width as i32);
+
+		for (level, (first, count)) in self.levels.iter().enumerate().rev() {
+			RESOLVE_COVERAGE.set(level == self.depth - 1);
+			NODE_OFFSET.set(*first as u32);
+			NODE_COUNT.set(*count as u32);
+			dispatch(*count);
+
+			unsafe {
+				gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+			}
+		}
+
+		unsafe {
+			gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+		}
+	}
+
+	// Binds what cone tracing reads: the node pool and the radiance bricks as a sampler
+	pub fn bind_for_tracing(&self, radiance_unit: u32) {
+		self.bind_nodes();
+		unsafe {
+			gl::BindTextureUnit(radiance_unit, self.radiance_bricks);
+		}
+	}
+
+	fn bind_nodes(&self) {
+		unsafe {
+			gl::BindBufferBase(
+				gl::SHADER_STORAGE_BUFFER,
+				OCTREE_NODE_BINDING,
+				self.node_pool,
+			);
+		}
+	}
+
+	fn bind_brick_image(&self, bricks: u32, unit: u32, format: GLenum) {
+		unsafe {
+			gl::BindImageTexture(unit, bricks, 0, gl::TRUE, 0, gl::READ_WRITE, format);
+		}
+	}
+
+	fn resolution_3d(&self) -> [i32; 3] {
+		[self.resolution as i32; 3]
+	}
+}
+
+impl Drop for SparseOctree {
+	fn drop(&mut self) {
+		self.release();
+	}
+}
+
+fn allocate_brick_pool(size: usize) -> u32 {
+	use gl::*;
+
+	let mut handle = 0;
+	unsafe {
+		CreateTextures(TEXTURE_3D, 1, &mut handle);
+		TextureParameteri(handle, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+		TextureParameteri(handle, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+		TextureParameteri(handle, TEXTURE_WRAP_R, CLAMP_TO_EDGE as i32);
+		TextureParameteri(handle, TEXTURE_MIN_FILTER, LINEAR as i32);
+		TextureParameteri(handle, TEXTURE_MAG_FILTER, LINEAR as i32);
+		TextureStorage3D(handle, 1, RGBA8, size as i32, size as i32, size as i32);
+	}
+
+	handle
+}
+
+// One invocation per item, spread over a second dimension when there are too many work groups for
+// the first one
+fn dispatch(items: usize) {
+	let groups = (items + WORK_GROUP_SIZE - 1) / WORK_GROUP_SIZE;
+	if groups == 0 {
+		return;
+	}
+
+	let x = groups.min(MAX_WORK_GROUPS);
+	let y = (groups + x - 1) / x;
+	unsafe {
+		gl::DispatchCompute(x as u32, y as u32, 1);
+	}
+}
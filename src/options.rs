@@ -0,0 +1,121 @@
+use crate::renderer::{AccumulationMode, ConservativeMode, Renderer, VoxelizationMode};
+use crate::textures::VolumeStorage;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(about = "Voxel Cone Tracing with Hybrid Voxelization")]
+pub struct Options {
+	/// Scene description file
+	#[structopt(default_value = "assets/scenes/sponza.ron")]
+	pub scene: String,
+
+	/// Volume resolution, overrides the scene's
+	#[structopt(short, long)]
+	pub resolution: Option<usize>,
+
+	/// Volume storage: dense 3D textures, a sparse voxel octree or clipmap cascades around the camera
+	#[structopt(long)]
+	pub storage: Option<VolumeStorage>,
+
+	/// Number of clipmap cascades, from 1 to 4
+	#[structopt(long)]
+	pub cascades: Option<usize>,
+
+	/// Voxelization method: fragment, hybrid or compute
+	#[structopt(long)]
+	pub voxelization: Option<VoxelizationMode>,
+
+	/// Conservative rasterization: none, nv, intel or geometry, falls back if unsupported
+	#[structopt(long)]
+	pub conservative: Option<ConservativeMode>,
+
+	/// How the samples of a voxel are combined: packed running averages or sums and a resolve pass
+	#[structopt(long)]
+	pub accumulation: Option<AccumulationMode>,
+
+	/// Triangle area cutoff between the small and large paths of the hybrid voxelizer
+	#[structopt(long)]
+	pub cutoff: Option<f32>,
+
+	/// Voxelizes into the voxel fragment list instead of the dense textures
+	#[structopt(long)]
+	pub fragment_list: bool,
+
+	/// Voxelizes every primitive each frame instead of the static ones only once
+	#[structopt(long)]
+	pub no_static_split: bool,
+
+	/// Voxelizes the interpolated vertex normals without the normal maps
+	#[structopt(long)]
+	pub no_normal_mapping: bool,
+
+	/// Voxelizes and lights the volume every frame, even when nothing it depends on changed
+	#[structopt(long)]
+	pub force_revoxelize: bool,
+
+	#[structopt(long, default_value = "1280")]
+	pub width: u32,
+
+	#[structopt(long, default_value = "720")]
+	pub height: u32,
+
+	/// Renders this many frames and exits
+	#[structopt(long)]
+	pub frames: Option<usize>,
+
+	/// Writes the pass timings to this file on exit
+	#[structopt(long, parse(from_os_str))]
+	pub output_csv: Option<PathBuf>,
+
+	/// Runs the benchmark sweep described by this file and exits
+	#[structopt(long, parse(from_os_str))]
+	pub benchmark: Option<PathBuf>,
+
+	/// Runs the accuracy sweep described by this file and exits
+	#[structopt(long, parse(from_os_str))]
+	pub accuracy: Option<PathBuf>,
+
+	/// Reads the shaders from this directory instead of the embedded ones and hot reloads them
+	#[structopt(long, parse(from_os_str))]
+	pub shader_dir: Option<PathBuf>,
+}
+
+impl Options {
+	// Command line settings take precedence over the ones in the scene file
+	pub fn apply(&self, renderer: &mut Renderer) {
+		if let Some(cascades) = self.cascades {
+			renderer.set_cascade_count(cascades);
+		}
+		match (self.storage, self.resolution) {
+			(Some(storage), Some(resolution)) => renderer.set_volume(storage, resolution),
+			(Some(storage), None) => renderer.set_storage(storage),
+			(None, Some(resolution)) => renderer.set_resolution(resolution),
+			(None, None) => (),
+		}
+		if let Some(mode) = self.voxelization {
+			renderer.set_voxelization_mode(mode);
+		}
+		if let Some(conservative) = self.conservative {
+			renderer.set_conservative_mode(conservative);
+		}
+		if let Some(accumulation) = self.accumulation {
+			renderer.accumulation = accumulation;
+		}
+		if let Some(cutoff) = self.cutoff {
+			renderer.cutoff = cutoff;
+		}
+		if self.fragment_list {
+			renderer.fragment_output = true;
+		}
+		if self.no_static_split {
+			renderer.static_split = false;
+		}
+		if self.no_normal_mapping {
+			renderer.normal_mapping = false;
+		}
+		if self.force_revoxelize {
+			renderer.force_revoxelize = true;
+		}
+	}
+}
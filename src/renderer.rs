@@ -1,20 +1,35 @@
+use crate::capabilities::{Feature, GlCapabilities};
+use crate::clipmap::{MAX_CASCADES, MIN_CLIPMAP_RESOLUTION};
+use crate::fragment_list::FragmentList;
 use crate::gl_timer::*;
-use crate::gl_utils::*;
 use crate::gpu_model::{GpuMaterial, GpuPrimitive};
+use crate::light_clusters::{LightBuffer, LightClusters};
+use crate::octree::{OctreePrograms, MAX_OCTREE_RESOLUTION};
 use crate::renderer_utils::*;
 use crate::scene::camera::*;
+use crate::scene::light::Light;
 use crate::scene::material::{Material, Texture};
 use crate::scene::model::Mesh;
+use crate::shader_layout::*;
+use crate::shader_manager::ShaderManager;
+use crate::shader_sources::ShaderSources;
+use crate::statistics::*;
+use failure::Error;
 use gl;
 use gl::types::*;
 use gl_helpers::*;
 use nalgebra_glm as glm;
+use serde::Deserialize;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::mem;
+use std::path::Path;
 use std::rc::Rc;
+use std::str::FromStr;
 
-use crate::textures::Volume;
+use crate::textures::{Volume, VolumeStorage, RADIANCE_MIPS};
+use crate::voxel_sums::VoxelSums;
+use crate::voxel_view::{SliceAxis, ViewedTexture, VoxelView};
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum RenderingMode {
@@ -25,52 +40,312 @@ pub enum RenderingMode {
 	Radiance,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Deserialize)]
 pub enum VoxelizationMode {
 	FragmentOnly,
 	Hybrid,
+	Compute,
+}
+
+impl VoxelizationMode {
+	pub fn feature(self) -> Feature {
+		match self {
+			VoxelizationMode::FragmentOnly => Feature::FragmentVoxelization,
+			VoxelizationMode::Hybrid => Feature::HybridVoxelization,
+			VoxelizationMode::Compute => Feature::ComputeVoxelization,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			VoxelizationMode::FragmentOnly => "fragment",
+			VoxelizationMode::Hybrid => "hybrid",
+			VoxelizationMode::Compute => "compute",
+		}
+	}
+}
+
+impl FromStr for VoxelizationMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<VoxelizationMode, String> {
+		match s {
+			"fragment" => Ok(VoxelizationMode::FragmentOnly),
+			"hybrid" => Ok(VoxelizationMode::Hybrid),
+			"compute" => Ok(VoxelizationMode::Compute),
+			_ => Err(format!(
+				"Unknown voxelization mode '{}', expected fragment, hybrid or compute",
+				s
+			)),
+		}
+	}
+}
+
+// How the voxelizers combine the samples that land in the same voxel of a dense volume
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum AccumulationMode {
+	// A running average packed in the RGBA8 textures with a compare and swap loop, the count in the
+	// alpha saturates at 255
+	Packed,
+	// 32 bit fixed point sums and a count per voxel, divided by a resolve pass
+	Sum,
+}
+
+impl AccumulationMode {
+	pub fn name(self) -> &'static str {
+		match self {
+			AccumulationMode::Packed => "packed",
+			AccumulationMode::Sum => "sum",
+		}
+	}
+}
+
+impl FromStr for AccumulationMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<AccumulationMode, String> {
+		match s {
+			"packed" => Ok(AccumulationMode::Packed),
+			"sum" => Ok(AccumulationMode::Sum),
+			_ => Err(format!(
+				"Unknown accumulation mode '{}', expected packed or sum",
+				s
+			)),
+		}
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum ConservativeMode {
+	None,
+	Nv,
+	Intel,
+	GeometryShader,
+}
+
+impl ConservativeMode {
+	const GL_NV_CONSERVATIVE_RASTERIZATION: u32 = 0x9346;
+	const GL_CONSERVATIVE_RASTERIZATION_INTEL: u32 = 0x83FE;
+
+	pub const ALL: [ConservativeMode; 4] = [
+		ConservativeMode::None,
+		ConservativeMode::Nv,
+		ConservativeMode::Intel,
+		ConservativeMode::GeometryShader,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			ConservativeMode::None => "none",
+			ConservativeMode::Nv => "nv",
+			ConservativeMode::Intel => "intel",
+			ConservativeMode::GeometryShader => "geometry",
+		}
+	}
+
+	pub fn is_supported(self, capabilities: &GlCapabilities) -> bool {
+		match self {
+			ConservativeMode::Nv => capabilities.supports(Feature::NvConservative),
+			ConservativeMode::Intel => capabilities.supports(Feature::IntelConservative),
+			ConservativeMode::None | ConservativeMode::GeometryShader => true,
+		}
+	}
+
+	// Modes to try, in order, when this one isn't available
+	fn fallbacks(self) -> &'static [ConservativeMode] {
+		match self {
+			ConservativeMode::Nv => &[ConservativeMode::Intel, ConservativeMode::GeometryShader],
+			ConservativeMode::Intel => &[ConservativeMode::Nv, ConservativeMode::GeometryShader],
+			ConservativeMode::None | ConservativeMode::GeometryShader => &[],
+		}
+	}
+
+	fn capability(self) -> Option<u32> {
+		match self {
+			ConservativeMode::Nv => Some(Self::GL_NV_CONSERVATIVE_RASTERIZATION),
+			ConservativeMode::Intel => Some(Self::GL_CONSERVATIVE_RASTERIZATION_INTEL),
+			ConservativeMode::None | ConservativeMode::GeometryShader => None,
+		}
+	}
+
+	// The geometry shaders dilate the triangles themselves
+	fn expands_triangles(self) -> bool {
+		self == ConservativeMode::GeometryShader
+	}
+
+	fn enable(self) {
+		if let Some(capability) = self.capability() {
+			unsafe {
+				gl::Enable(capability);
+			}
+		}
+	}
+
+	fn disable(self) {
+		if let Some(capability) = self.capability() {
+			unsafe {
+				gl::Disable(capability);
+			}
+		}
+	}
+}
+
+impl FromStr for ConservativeMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<ConservativeMode, String> {
+		match s {
+			"none" => Ok(ConservativeMode::None),
+			"nv" => Ok(ConservativeMode::Nv),
+			"intel" => Ok(ConservativeMode::Intel),
+			"geometry" => Ok(ConservativeMode::GeometryShader),
+			_ => Err(format!(
+				"Unknown conservative mode '{}', expected none, nv, intel or geometry",
+				s
+			)),
+		}
+	}
+}
+
+// The primitives a voxelization goes over
+#[derive(Copy, Clone, PartialEq)]
+enum PrimitiveSet {
+	All,
+	Static,
+	Dynamic,
+}
+
+impl PrimitiveSet {
+	fn contains(self, primitive: &GpuPrimitive) -> bool {
+		match self {
+			PrimitiveSet::All => true,
+			PrimitiveSet::Static => !primitive.is_dynamic(),
+			PrimitiveSet::Dynamic => primitive.is_dynamic(),
+		}
+	}
+}
+
+// Where a voxelization writes its samples
+#[derive(Copy, Clone, PartialEq)]
+enum VoxelOutput {
+	Textures,
+	Sums,
+	FragmentList,
+}
+
+// What the static voxels were built from, they are voxelized again once any of it changes
+#[derive(PartialEq)]
+struct StaticVoxelization {
+	mode: VoxelizationMode,
+	accumulation: AccumulationMode,
+	normal_mapping: bool,
+	conservative_mode: ConservativeMode,
+	cutoff: f32,
+	resolution: [i32; 3],
+	translation: glm::Vec3,
+	scaling: glm::Vec3,
+	transforms: Vec<[f32; 16]>,
+}
+
+// Everything the radiance of the volume depends on. While it stays the same, the volume is neither
+// voxelized nor lit again and cone tracing reuses the previous radiance
+#[derive(PartialEq)]
+struct VolumeState {
+	storage: VolumeStorage,
+	static_voxels: StaticVoxelization,
+	dynamic_transforms: Vec<[f32; 16]>,
+	lights: Vec<Light>,
+	fragment_output: bool,
+	static_split: bool,
 }
 
 pub struct Renderer {
 	viewport_size: (usize, usize),
 	pub rendering_mode: RenderingMode,
 	pub voxelization_mode: VoxelizationMode,
+	// Only used by dense volumes, the clipmap and the octree always average in place
+	pub accumulation: AccumulationMode,
+	// Perturb the voxel normals with the normal maps, as the scene is shaded
+	pub normal_mapping: bool,
 	primitives: Vec<GpuPrimitive>,
 	materials: HashMap<String, Rc<GpuMaterial>>,
 	textures: HashMap<String, Rc<GLTexture>>,
 	pbr_program: GLProgram,
 	pub lights: Vec<Light>,
+	light_buffer: LightBuffer,
+	light_clusters: LightClusters,
+	light_cull_program: GLProgram,
 	depth_map: GLTexture,
 	depth_map_framebuffer: GLFramebuffer,
 	depth_program: GLProgram,
 	volume_view_program: GLProgram,
+	voxel_compact_program: GLProgram,
+	voxel_slice_program: GLProgram,
+	voxel_view: VoxelView,
+	voxel_resolve_program: GLProgram,
+	voxel_sums: VoxelSums,
 	volume_scene: Volume,
 	voxelize_program: GLProgram,
+	voxelize_compute_program: GLProgram,
 	classify_program: GLProgram,
+	count_voxels_program: GLProgram,
 	bounds_program: GLProgram,
-	clear_program: GLProgram,
 	inject_program: GLProgram,
+	octree_programs: OctreePrograms,
+	fragment_list: FragmentList,
+	// Fragments appended to the list by the last voxelization that wrote to it
+	fragment_count: Option<usize>,
+	voxelization_framebuffer: EmptyFramebuffer,
 	triangle_counter: AtomicCounter,
 	indirect_command: IndirectCommand,
 	indices_buffer: IndicesBuffer,
+	statistics_counters: StatisticsCounters,
+	pipeline_queries: Option<PipelineQueries>,
+	statistics: Option<VoxelizationStatistics>,
 	timer: GlTimer,
-	pub nv_conservative: bool,
+	// Only present when the shaders are read from a directory
+	shader_manager: Option<ShaderManager>,
+	capabilities: GlCapabilities,
+	conservative_mode: ConservativeMode,
+	supported_conservative_modes: Vec<ConservativeMode>,
 	pub show_bounds: bool,
+	// Mip drawn by the voxel views, the attribute textures only have the first one
+	pub voxel_view_mip: usize,
+	// The voxel views and slices leave out the voxels whose alpha is at or under this
+	pub voxel_alpha_threshold: f32,
 	pub cutoff: f32,
+	pub collect_statistics: bool,
+	// Voxelize into the fragment list instead of the dense textures, the octree always does
+	pub fragment_output: bool,
+	// Only revoxelize the slabs the clipmap cascades uncover when they move
+	pub clipmap_incremental: bool,
+	// World bounds of the dynamic primitives when they were last voxelized into the clipmap
+	clipmap_dynamic_bounds: Vec<(glm::Vec3, glm::Vec3)>,
+	// Keep the static primitives of a dense volume and only voxelize the dynamic ones every frame
+	pub static_split: bool,
+	// What the static voxels of a dense volume, or the cascades of a clipmap, were voxelized from
+	static_voxels: Option<StaticVoxelization>,
+	// Voxelize and light the volume every frame even when nothing changed, to time it
+	pub force_revoxelize: bool,
+	volume_state: Option<VolumeState>,
 }
 
 impl Renderer {
-	const GL_NV_CONSERVATIVE_RASTERIZATION: u32 = 0x9346;
-
 	pub fn new(
 		window_gl: &glutin::WindowedContext<glutin::PossiblyCurrent>,
 		logical_size: glutin::dpi::LogicalSize,
 		resolution: usize,
-		conservative: bool,
-	) -> Renderer {
+		storage: VolumeStorage,
+		conservative_mode: ConservativeMode,
+		shader_sources: &ShaderSources,
+	) -> Result<Renderer, Error> {
 		gl::load_with(|symbol| window_gl.get_proc_address(symbol) as *const _);
+
+		let capabilities = GlCapabilities::probe();
+		capabilities.print_report();
+		capabilities.check()?;
+
 		gl_set_defaults();
-		print_opengl_diagnostics();
 
 		unsafe {
 			gl::Enable(gl::PROGRAM_POINT_SIZE);
@@ -83,36 +358,83 @@ impl Renderer {
 		depth_map_framebuffer.unbind();
 
 		// Volume setup
-		let volume_view_program = load_voxel_view_program();
-		let volume_scene = Volume::new(resolution, &volume_view_program);
+		let (storage, resolution) = supported_volume(&capabilities, storage, resolution);
+		let volume_view_program = load_program(shader_sources, &VOXEL_VIEW_PROGRAM);
+		let volume_scene = Volume::new(
+			resolution,
+			storage,
+			load_program(shader_sources, &MIPMAP_PROGRAM),
+		);
+
+		let supported_conservative_modes = ConservativeMode::ALL
+			.iter()
+			.cloned()
+			.filter(|mode| mode.is_supported(&capabilities))
+			.collect();
 
-		Renderer {
+		let mut renderer = Renderer {
 			viewport_size: (logical_size.width as usize, logical_size.height as usize),
 			rendering_mode: RenderingMode::Scene,
 			voxelization_mode: VoxelizationMode::Hybrid,
+			accumulation: AccumulationMode::Packed,
+			normal_mapping: true,
 			primitives: Vec::new(),
 			materials: HashMap::new(),
 			textures: HashMap::new(),
-			pbr_program: load_pbr_program(),
+			pbr_program: load_program(shader_sources, &PBR_PROGRAM),
 			lights: load_lights(),
+			light_buffer: LightBuffer::new(),
+			light_clusters: LightClusters::new(),
+			light_cull_program: load_program(shader_sources, &LIGHT_CULL_PROGRAM),
 			depth_map,
 			depth_map_framebuffer,
-			depth_program: load_depth_program(),
+			depth_program: load_program(shader_sources, &DEPTH_PROGRAM),
 			volume_view_program,
+			voxel_compact_program: load_program(shader_sources, &VOXEL_COMPACT_PROGRAM),
+			voxel_slice_program: load_program(shader_sources, &VOXEL_SLICE_PROGRAM),
+			voxel_view: VoxelView::new(),
+			voxel_resolve_program: load_program(shader_sources, &VOXEL_RESOLVE_PROGRAM),
+			voxel_sums: VoxelSums::new(),
 			volume_scene,
-			voxelize_program: load_voxelize_program(),
-			classify_program: load_classify_program(),
-			bounds_program: load_bounds_program(),
-			clear_program: load_clear_program(),
-			inject_program: load_radiance_injection_program(),
+			voxelize_program: load_program(shader_sources, &VOXELIZE_PROGRAM),
+			voxelize_compute_program: load_program(shader_sources, &VOXELIZE_COMPUTE_PROGRAM),
+			classify_program: load_program(shader_sources, &CLASSIFY_PROGRAM),
+			count_voxels_program: load_program(shader_sources, &COUNT_VOXELS_PROGRAM),
+			bounds_program: load_program(shader_sources, &BOUNDS_PROGRAM),
+			inject_program: load_program(shader_sources, &RADIANCE_INJECTION_PROGRAM),
+			octree_programs: OctreePrograms::load(shader_sources),
+			fragment_list: FragmentList::new(1 << 20),
+			fragment_count: None,
+			voxelization_framebuffer: EmptyFramebuffer::new(),
 			triangle_counter: AtomicCounter::new(),
 			indirect_command: IndirectCommand::new(),
 			indices_buffer: IndicesBuffer::new(),
-			timer: GlTimer::new(10, 1200),
-			nv_conservative: conservative,
+			statistics_counters: StatisticsCounters::new(),
+			pipeline_queries: PipelineQueries::new(&capabilities),
+			statistics: None,
+			timer: GlTimer::new(1200),
+			shader_manager: shader_sources
+				.directory()
+				.map(|directory| ShaderManager::new(directory, &ALL_PROGRAMS)),
+			capabilities,
+			conservative_mode: ConservativeMode::None,
+			supported_conservative_modes,
 			show_bounds: false,
+			voxel_view_mip: 0,
+			voxel_alpha_threshold: 0.0,
 			cutoff: 1.0,
-		}
+			collect_statistics: false,
+			fragment_output: false,
+			clipmap_incremental: true,
+			clipmap_dynamic_bounds: Vec::new(),
+			static_split: true,
+			static_voxels: None,
+			force_revoxelize: false,
+			volume_state: None,
+		};
+
+		renderer.set_conservative_mode(conservative_mode);
+		Ok(renderer)
 	}
 
 	#[allow(dead_code)]
@@ -148,41 +470,39 @@ impl Renderer {
 		self.depth_map_framebuffer.unbind();
 	}
 
-	fn clear_volume(&self) {
-		self.clear_program.bind();
-
-		self.volume_scene.bind_texture_albedo(0);
-		self.volume_scene.bind_texture_normal(1);
-		self.volume_scene.bind_texture_emission(2);
-		self.volume_scene.bind_texture_radiance(3);
-
-		self.volume_scene.draw();
+	fn clear_volume(&mut self) {
+		self.timer.begin("clear_volume");
+		self.volume_scene.clear();
+		self.timer.end("clear_volume");
 	}
 
-	fn inject_light(&mut self) {
+	fn inject_light(&mut self, overlay: bool) {
 		self.timer.begin("inject_light");
 
 		self.inject_program.bind();
-
-		let (positions, colors) = lights_to_soa(&self.lights);
-
-		self
-			.inject_program
-			.get_uniform("u_light_position")
-			.set_3fv(&positions[..]);
-		self
-			.inject_program
-			.get_uniform("u_light_color")
-			.set_3fv(&colors[..]);
-		self
-			.inject_program
-			.get_uniform("u_num_lights")
-			.set_1i(self.lights.len() as i32);
-
-		self.volume_scene.bind_texture_albedo(0);
-		self.volume_scene.bind_texture_normal(1);
-		self.volume_scene.bind_texture_emission(2);
-		self.volume_scene.bind_image_radiance(3);
+		set_voxel_lighting_uniforms(
+			&self.inject_program,
+			&self.light_buffer,
+			self.volume_scene.translation(),
+			self.volume_scene.scaling(),
+		);
+		VOXEL_OFFSET.set(&[0; 3]);
+		DYNAMIC_OVERLAY.set(overlay);
+
+		self.volume_scene.bind_texture_albedo(ALBEDO_UNIT);
+		self.volume_scene.bind_texture_normal(NORMAL_UNIT);
+		self.volume_scene.bind_texture_emission(EMISSION_UNIT);
+		self.volume_scene.bind_image_radiance(RADIANCE_UNIT);
+		if overlay {
+			self.volume_scene.bind_textures_overlay(
+				OVERLAY_ALBEDO_UNIT,
+				OVERLAY_NORMAL_UNIT,
+				OVERLAY_EMISSION_UNIT,
+			);
+			unsafe {
+				gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+			}
+		}
 
 		let resolution = &self.volume_scene.resolution();
 		self
@@ -190,18 +510,6 @@ impl Renderer {
 			.get_uniform("u_resolution")
 			.set_3i(1, resolution);
 
-		let position = *self.volume_scene.translation();
-		let scale = *self.volume_scene.scaling();
-
-		self
-			.inject_program
-			.get_uniform("u_volume_center")
-			.set_3f(1, &position.into());
-		self
-			.inject_program
-			.get_uniform("u_volume_scale")
-			.set_3f(1, &scale.into());
-
 		unsafe {
 			gl::DispatchCompute(
 				resolution[0] as u32 / 8,
@@ -244,57 +552,99 @@ impl Renderer {
 		gl_draw_arrays(DrawMode::Lines, 0, 24);
 	}
 
-	fn voxelize(&mut self) {
-		match self.voxelization_mode {
-			VoxelizationMode::FragmentOnly => self.voxelize_fragment(),
-			VoxelizationMode::Hybrid => self.voxelize_hybrid(),
+	// Binds where the voxelizers write: the textures of the target, the sums or the fragment list
+	fn bind_voxelization_output(&self, target: &VoxelTarget, output: VoxelOutput) {
+		match output {
+			VoxelOutput::Textures => target.bind_images(),
+			VoxelOutput::Sums => self.voxel_sums.bind_unit(VOXEL_SUMS_BINDING),
+			VoxelOutput::FragmentList => self.fragment_list.bind_unit(FRAGMENT_LIST_BINDING),
 		}
 	}
 
-	fn voxelize_hybrid(&mut self) {
+	// Voxelizes into the textures of a dense target with the accumulation mode
+	fn voxelize_dense(&mut self, target: &VoxelTarget, primitives: PrimitiveSet) {
+		match self.accumulation {
+			AccumulationMode::Packed => {
+				// The sums are only kept while they are used
+				self.voxel_sums.release();
+				self.voxelize(target, primitives, VoxelOutput::Textures);
+			}
+			AccumulationMode::Sum => {
+				let resolution = target.resolution;
+				let voxels = (resolution[0] * resolution[1] * resolution[2]) as usize;
+				self.voxel_sums.reserve(voxels);
+				self.voxel_sums.clear();
+				self.voxelize(target, primitives, VoxelOutput::Sums);
+
+				self.timer.begin("resolve_sums");
+				self.voxel_sums.resolve(&self.voxel_resolve_program, target);
+				self.timer.end("resolve_sums");
+			}
+		}
+	}
+
+	fn voxelize(&mut self, target: &VoxelTarget, primitives: PrimitiveSet, output: VoxelOutput) {
+		match self.effective_voxelization_mode() {
+			VoxelizationMode::FragmentOnly => self.voxelize_fragment(target, primitives, output),
+			VoxelizationMode::Hybrid => self.voxelize_hybrid(target, primitives, output),
+			VoxelizationMode::Compute => self.voxelize_compute(target, primitives, output),
+		}
+	}
+
+	fn voxelize_hybrid(
+		&mut self,
+		target: &VoxelTarget,
+		primitives: PrimitiveSet,
+		output: VoxelOutput,
+	) {
 		self.timer.begin("voxelize_hybrid");
 
-		let resolution = &self.volume_scene.resolution();
+		let resolution = &target.resolution;
 
 		gl_set_depth_write(false);
 		gl_set_cull_face(CullFace::None);
+		self.voxelization_framebuffer.bind(resolution[0] as usize);
 		gl_set_viewport(0, 0, resolution[0] as usize, resolution[1] as usize);
 		gl_clear(true, true, false);
 		unsafe {
 			gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
-
-			if self.nv_conservative {
-				gl::Enable(Self::GL_NV_CONSERVATIVE_RASTERIZATION);
-			}
 		};
+		self.conservative_mode.enable();
+
+		let expand_triangles = self.conservative_mode.expands_triangles();
 
-		let pv: [f32; 16] = voxelization_pv(&self.volume_scene);
+		let pv = target.pv;
 
 		// Shared uniforms
 		self.classify_program.bind();
-		unsafe {
-			gl::Uniform3iv(0, 1, resolution as *const _);
-			gl::UniformMatrix4fv(1, 1, gl::FALSE, (&pv) as *const _);
-			gl::Uniform1i(2, !self.nv_conservative as i32);
-			gl::Uniform1f(3, self.cutoff);
-		}
+		RESOLUTION.set(resolution);
+		PV.set(&pv);
+		CUTOFF.set(self.cutoff);
+		COLLECT_STATISTICS.set(self.collect_statistics);
+		STORE_FRAGMENTS.set(output == VoxelOutput::FragmentList);
+		ACCUMULATE_SUMS.set(output == VoxelOutput::Sums);
+		target.region.set_uniforms();
 		self.voxelize_program.bind();
-		unsafe {
-			gl::Uniform3iv(0, 1, resolution as *const _);
-			gl::UniformMatrix4fv(1, 1, gl::FALSE, (&pv) as *const _);
-			gl::Uniform1i(2, !self.nv_conservative as i32);
-		}
+		RESOLUTION.set(resolution);
+		PV.set(&pv);
+		EXPAND_TRIANGLES.set(expand_triangles);
+		COLLECT_STATISTICS.set(self.collect_statistics);
+		STORE_FRAGMENTS.set(output == VoxelOutput::FragmentList);
+		ACCUMULATE_SUMS.set(output == VoxelOutput::Sums);
+		target.region.set_uniforms();
 
-		// Image bindings
-		self.volume_scene.bind_image_albedo(0);
-		self.volume_scene.bind_image_normal(1);
-		self.volume_scene.bind_image_emission(2);
+		self.bind_voxelization_output(target, output);
 
 		// Indirect and indexing stuff
-		self.triangle_counter.bind_unit(0);
-		self.indices_buffer.bind_image_texture(3);
-		self.indirect_command.bind_image_texture(4);
-		for primitive in &self.primitives {
+		self.triangle_counter.bind_unit(TRIANGLE_COUNTER_BINDING);
+		self
+			.statistics_counters
+			.bind_unit(STATISTICS_COUNTER_BINDING);
+		self.indices_buffer.bind_image_texture(LARGE_INDICES_UNIT);
+		self
+			.indirect_command
+			.bind_image_texture(LARGE_INDIRECT_UNIT);
+		for primitive in self.primitives.iter().filter(|p| primitives.contains(p)) {
 			primitive.bind();
 
 			self.classify_program.bind();
@@ -308,6 +658,9 @@ impl Renderer {
 				.classify_program
 				.get_uniform("albedo_map")
 				.set_sampler_2d(&mat.albedo(), 0);
+			mat.normal().bind_unit(NORMAL_MAP_UNIT);
+			let normal_mapping = self.normal_mapping && primitive.has_tangents();
+			NORMAL_MAPPING.set(normal_mapping);
 
 			gl_draw_elements(
 				DrawMode::Triangles,
@@ -332,6 +685,7 @@ impl Renderer {
 					.voxelize_program
 					.get_uniform("albedo_map")
 					.set_sampler_2d(&mat.albedo(), 0);
+				NORMAL_MAPPING.set(normal_mapping);
 
 				gl::DrawElementsIndirect(gl::TRIANGLES, gl::UNSIGNED_INT, ptr::null());
 			}
@@ -339,49 +693,49 @@ impl Renderer {
 
 		unsafe {
 			gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
-			if self.nv_conservative {
-				gl::Disable(Self::GL_NV_CONSERVATIVE_RASTERIZATION);
-			}
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::SHADER_STORAGE_BARRIER_BIT);
 		}
+		self.conservative_mode.disable();
+		self.voxelization_framebuffer.unbind();
 
 		self.timer.end("voxelize_hybrid");
 	}
 
-	fn voxelize_fragment(&mut self) {
+	fn voxelize_fragment(
+		&mut self,
+		target: &VoxelTarget,
+		primitives: PrimitiveSet,
+		output: VoxelOutput,
+	) {
 		self.timer.begin("voxelize_fragment");
 
-		let resolution = &self.volume_scene.resolution();
+		let resolution = &target.resolution;
 
 		gl_set_depth_write(false);
 		gl_set_cull_face(CullFace::None);
+		self.voxelization_framebuffer.bind(resolution[0] as usize);
 		gl_set_viewport(0, 0, resolution[0] as usize, resolution[1] as usize);
 		gl_clear(true, true, false);
 		unsafe {
 			gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
 			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
-			if self.nv_conservative {
-				gl::Enable(Self::GL_NV_CONSERVATIVE_RASTERIZATION);
-			}
 		};
+		self.conservative_mode.enable();
 
 		self.voxelize_program.bind();
-		unsafe {
-			gl::Uniform1i(2, !self.nv_conservative as i32);
-		}
+		EXPAND_TRIANGLES.set(self.conservative_mode.expands_triangles());
+		COLLECT_STATISTICS.set(self.collect_statistics);
+		STORE_FRAGMENTS.set(output == VoxelOutput::FragmentList);
+		ACCUMULATE_SUMS.set(output == VoxelOutput::Sums);
 		self
-			.voxelize_program
-			.get_uniform("u_resolution")
-			.set_3i(1, resolution);
-
-		let pv: [f32; 16] = voxelization_pv(&self.volume_scene);
-
-		self.voxelize_program.get_uniform("pv").set_mat4f(&pv);
-
-		self.volume_scene.bind_image_albedo(0);
-		self.volume_scene.bind_image_normal(1);
-		self.volume_scene.bind_image_emission(2);
-
-		for primitive in &self.primitives {
+			.statistics_counters
+			.bind_unit(STATISTICS_COUNTER_BINDING);
+		RESOLUTION.set(resolution);
+		PV.set(&target.pv);
+		target.region.set_uniforms();
+		self.bind_voxelization_output(target, output);
+
+		for primitive in self.primitives.iter().filter(|p| primitives.contains(p)) {
 			primitive.bind();
 
 			self
@@ -394,6 +748,8 @@ impl Renderer {
 				.voxelize_program
 				.get_uniform("albedo_map")
 				.set_sampler_2d(&mat.albedo(), 0);
+			mat.normal().bind_unit(NORMAL_MAP_UNIT);
+			NORMAL_MAPPING.set(self.normal_mapping && primitive.has_tangents());
 
 			gl_draw_elements(
 				DrawMode::Triangles,
@@ -405,26 +761,440 @@ impl Renderer {
 
 		unsafe {
 			gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
-			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
-			if self.nv_conservative {
-				gl::Disable(Self::GL_NV_CONSERVATIVE_RASTERIZATION);
-			}
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::SHADER_STORAGE_BARRIER_BIT);
 			// gl::MemoryBarrier(gl::ALL_BARRIER_BITS);
 		}
+		self.conservative_mode.disable();
+		self.voxelization_framebuffer.unbind();
 		self.timer.end("voxelize_fragment");
 	}
 
-	pub fn render(&mut self, camera: &Camera) {
-		self.timer.begin_frame();
-		// self.render_to_shadow_map();
+	fn voxelize_compute(
+		&mut self,
+		target: &VoxelTarget,
+		primitives: PrimitiveSet,
+		output: VoxelOutput,
+	) {
+		self.timer.begin("voxelize_compute");
+
+		let resolution = &target.resolution;
+
+		self.voxelize_compute_program.bind();
+		RESOLUTION.set(resolution);
+		PV.set(&target.pv);
+		COLLECT_STATISTICS.set(self.collect_statistics);
+		STORE_FRAGMENTS.set(output == VoxelOutput::FragmentList);
+		ACCUMULATE_SUMS.set(output == VoxelOutput::Sums);
+		target.region.set_uniforms();
+		self.bind_voxelization_output(target, output);
+		self
+			.statistics_counters
+			.bind_unit(STATISTICS_COUNTER_BINDING);
 
-		self.clear_volume();
-		self.voxelize();
-		self.inject_light();
+		for primitive in self.primitives.iter().filter(|p| primitives.contains(p)) {
+			primitive.bind_storage(VERTEX_STORAGE_BINDING, INDEX_STORAGE_BINDING);
+
+			let program = &self.voxelize_compute_program;
+			program
+				.get_uniform("model")
+				.set_mat4f(&primitive.model_matrix_raw());
+
+			let triangle_count = primitive.count_vertices() / 3;
+			program
+				.get_uniform("u_triangle_count")
+				.set_1i(triangle_count as i32);
+			program
+				.get_uniform("u_tex_coords_offset")
+				.set_1i(primitive.tex_coords_offset() as i32);
+			program
+				.get_uniform("u_normals_offset")
+				.set_1i(primitive.normals_offset() as i32);
+			program
+				.get_uniform("u_tangents_offset")
+				.set_1i(primitive.tangents_offset() as i32);
+
+			let mat = &primitive.material();
+			program
+				.get_uniform("albedo_map")
+				.set_sampler_2d(&mat.albedo(), 0);
+			mat.normal().bind_unit(NORMAL_MAP_UNIT);
+			NORMAL_MAPPING.set(self.normal_mapping && primitive.has_tangents());
+
+			unsafe {
+				gl::DispatchCompute((triangle_count as u32 + 63) / 64, 1, 1);
+			}
+		}
+
+		unsafe {
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::SHADER_STORAGE_BARRIER_BIT);
+		}
+
+		self.timer.end("voxelize_compute");
+	}
+
+	fn count_occupied_voxels(&self) {
+		let resolution = &self.volume_scene.resolution();
+
+		self.count_voxels_program.bind();
+		unsafe {
+			gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+		}
+		RESOLUTION.set(resolution);
+
+		self.volume_scene.bind_texture_albedo(ALBEDO_UNIT);
+		self
+			.statistics_counters
+			.bind_unit(STATISTICS_COUNTER_BINDING);
+
+		unsafe {
+			gl::DispatchCompute(
+				(resolution[0] as u32 + 7) / 8,
+				(resolution[1] as u32 + 7) / 8,
+				(resolution[2] as u32 + 7) / 8,
+			);
+		}
+	}
+
+	// Voxelizes into the fragment list, growing it and voxelizing again when the fragments didn't
+	// fit. Returns how many were appended
+	fn voxelize_into_fragment_list(&mut self) -> usize {
+		let count = loop {
+			self.fragment_list.clear();
+			let target = self.volume_scene.voxel_target();
+			self.voxelize(&target, PrimitiveSet::All, VoxelOutput::FragmentList);
+
+			let count = self.fragment_list.count();
+			if count <= self.fragment_list.capacity() {
+				break count;
+			}
+
+			println!(
+				"{} voxel fragments don't fit in a list of {}, growing it",
+				count,
+				self.fragment_list.capacity()
+			);
+			self.fragment_list.reserve(count);
+		};
+
+		self.fragment_count = Some(count);
+		count
+	}
+
+	// Rebuilds the octree from the fragment list
+	fn update_octree(&mut self) {
+		let fragment_count = self.voxelize_into_fragment_list();
+
+		let center = *self.volume_scene.translation();
+		let scale = *self.volume_scene.scaling();
+		let octree = self
+			.volume_scene
+			.octree_mut()
+			.expect("The volume isn't stored in an octree");
+
+		self.timer.begin("build_octree");
+		octree.build(&self.octree_programs, &self.fragment_list, fragment_count);
+		self.timer.end("build_octree");
+
+		self.timer.begin("inject_light");
+		let inject_program = &self.octree_programs.inject;
+		inject_program.bind();
+		set_voxel_lighting_uniforms(inject_program, &self.light_buffer, &center, &scale);
+		octree.inject(&self.fragment_list);
+		self.timer.end("inject_light");
+
+		self.timer.begin("filter_octree");
+		octree.filter(&self.octree_programs.filter);
+		self.timer.end("filter_octree");
+	}
+
+	// Recenters the cascades on the camera, voxelizes what they uncovered and where the dynamic
+	// primitives moved, and lights all of them. The light is kept when nothing was voxelized, unless
+	// `relight` is set. When the static primitives or the voxelization settings changed, every
+	// cascade is voxelized again
+	fn update_clipmap(&mut self, camera: &Camera, relight: bool) {
+		let scaling = self.volume_scene.scaling();
+		let base_extent = scaling.x.max(scaling.y).max(scaling.z);
+		let current = self.static_voxelization();
+		let revoxelize = self.static_voxels.as_ref() != Some(&current);
+		self.static_voxels = Some(current);
+		let incremental = self.clipmap_incremental && !revoxelize;
+		let mut updates = self
+			.volume_scene
+			.clipmap_mut()
+			.expect("The volume isn't stored in a clipmap")
+			.update(&camera.position, base_extent, incremental);
+
+		// The voxels a dynamic primitive left and the ones it moved into
+		let dynamic_bounds: Vec<_> = self
+			.primitives
+			.iter()
+			.filter(|primitive| primitive.is_dynamic())
+			.map(|primitive| primitive.world_bounds())
+			.collect();
+		if dynamic_bounds != self.clipmap_dynamic_bounds {
+			let clipmap = self.volume_scene.clipmap().unwrap();
+			for (min, max) in self.clipmap_dynamic_bounds.iter().chain(&dynamic_bounds) {
+				updates.extend(clipmap.clear_box(min, max));
+			}
+			self.clipmap_dynamic_bounds = dynamic_bounds;
+		}
+
+		for update in &updates {
+			for region in &update.regions {
+				let target = self
+					.volume_scene
+					.clipmap()
+					.unwrap()
+					.voxel_target(update.cascade, region);
+				self.voxelize(&target, PrimitiveSet::All, VoxelOutput::Textures);
+			}
+		}
+		if updates.is_empty() && !relight {
+			return;
+		}
+
+		let clipmap = self.volume_scene.clipmap().unwrap();
+		let resolution = self.volume_scene.resolution();
+
+		self.timer.begin("inject_light");
+		clipmap.clear_radiance();
+		self.inject_program.bind();
+		self
+			.inject_program
+			.get_uniform("u_resolution")
+			.set_3i(1, &resolution);
+		unsafe {
+			gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT | gl::TEXTURE_UPDATE_BARRIER_BIT);
+		}
+
+		for cascade in 0..clipmap.cascade_count() {
+			let extent = clipmap.extent(cascade);
+			let scale = glm::vec3(extent, extent, extent);
+			// world_coordinate puts the corner of the volume at 0.505 of its scale from the center
+			let center = clipmap.center(cascade) + scale * 0.005;
+			set_voxel_lighting_uniforms(&self.inject_program, &self.light_buffer, &center, &scale);
+			VOXEL_OFFSET.set(&clipmap.offset(cascade));
+			clipmap.bind_for_injection(cascade);
+
+			unsafe {
+				gl::DispatchCompute(
+					(resolution[0] as u32 + 7) / 8,
+					(resolution[1] as u32 + 7) / 8,
+					(resolution[2] as u32 + 7) / 8,
+				);
+			}
+		}
+
+		unsafe {
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+		}
+		self.timer.end("inject_light");
 
 		self.timer.begin("generate_mipmap");
-		self.volume_scene.generate_mipmap();
+		clipmap.generate_mipmaps();
 		self.timer.end("generate_mipmap");
+	}
+
+	fn volume_state(&self) -> VolumeState {
+		VolumeState {
+			storage: self.volume_scene.storage(),
+			static_voxels: self.static_voxelization(),
+			dynamic_transforms: self
+				.primitives
+				.iter()
+				.filter(|primitive| primitive.is_dynamic())
+				.map(|primitive| primitive.model_matrix_raw())
+				.collect(),
+			lights: self.lights.clone(),
+			fragment_output: self.fragment_output,
+			static_split: self.static_split,
+		}
+	}
+
+	fn static_voxelization(&self) -> StaticVoxelization {
+		StaticVoxelization {
+			mode: self.effective_voxelization_mode(),
+			accumulation: self.accumulation,
+			normal_mapping: self.normal_mapping,
+			conservative_mode: self.conservative_mode,
+			cutoff: self.cutoff,
+			resolution: self.volume_scene.resolution(),
+			translation: *self.volume_scene.translation(),
+			scaling: *self.volume_scene.scaling(),
+			transforms: self
+				.primitives
+				.iter()
+				.filter(|primitive| !primitive.is_dynamic())
+				.map(|primitive| primitive.model_matrix_raw())
+				.collect(),
+		}
+	}
+
+	// Voxelizes the static primitives when what they were voxelized from changed, then the dynamic
+	// ones into the overlay. Returns whether there were dynamic primitives to voxelize
+	fn voxelize_split(&mut self) -> bool {
+		let current = self.static_voxelization();
+		if self.static_voxels.as_ref() != Some(&current) {
+			self.timer.begin("voxelize_static");
+			self.clear_volume();
+			let target = self.volume_scene.voxel_target();
+			self.voxelize_dense(&target, PrimitiveSet::Static);
+			self.timer.end("voxelize_static");
+			self.static_voxels = Some(current);
+		}
+
+		self.volume_scene.clear_overlay();
+		if !self
+			.primitives
+			.iter()
+			.any(|primitive| primitive.is_dynamic())
+		{
+			return false;
+		}
+
+		let target = self.volume_scene.overlay_target();
+		self.voxelize_dense(&target, PrimitiveSet::Dynamic);
+		true
+	}
+
+	// Clears and voxelizes the scene without lighting or drawing anything
+	pub fn voxelize_volume(&mut self) {
+		self.clear_volume();
+		self.static_voxels = None;
+		self.volume_state = None;
+		let target = self.volume_scene.voxel_target();
+		self.voxelize_dense(&target, PrimitiveSet::All);
+	}
+
+	fn reload_shaders(&mut self) {
+		let reloaded = match &mut self.shader_manager {
+			Some(shader_manager) => shader_manager.poll(),
+			None => return,
+		};
+
+		// The new programs may voxelize or light differently
+		if !reloaded.is_empty() {
+			self.static_voxels = None;
+			self.volume_state = None;
+		}
+
+		for (id, program) in reloaded {
+			let slot = match id {
+				ProgramId::Pbr => &mut self.pbr_program,
+				ProgramId::Depth => &mut self.depth_program,
+				ProgramId::VoxelView => &mut self.volume_view_program,
+				ProgramId::VoxelCompact => &mut self.voxel_compact_program,
+				ProgramId::VoxelSlice => &mut self.voxel_slice_program,
+				ProgramId::VoxelResolve => &mut self.voxel_resolve_program,
+				ProgramId::Voxelize => &mut self.voxelize_program,
+				ProgramId::Classify => &mut self.classify_program,
+				ProgramId::VoxelizeCompute => &mut self.voxelize_compute_program,
+				ProgramId::Bounds => &mut self.bounds_program,
+				ProgramId::RadianceInjection => &mut self.inject_program,
+				ProgramId::LightCull => &mut self.light_cull_program,
+				ProgramId::CountVoxels => &mut self.count_voxels_program,
+				ProgramId::Mipmap => {
+					self.volume_scene.set_mipmap_program(program);
+					continue;
+				}
+				ProgramId::OctreeFlag => &mut self.octree_programs.flag,
+				ProgramId::OctreeAllocate => &mut self.octree_programs.allocate,
+				ProgramId::OctreeStore => &mut self.octree_programs.store,
+				ProgramId::OctreeInject => &mut self.octree_programs.inject,
+				ProgramId::OctreeFilter => &mut self.octree_programs.filter,
+			};
+			*slot = program;
+		}
+	}
+
+	// The sums are kept between voxelizations, so they stay allocated once used
+	pub fn voxel_sums_bytes(&self) -> usize {
+		self.voxel_sums.memory_bytes()
+	}
+
+	fn collects_statistics(&self) -> bool {
+		self.collect_statistics && self.capabilities.supports(Feature::VoxelizationStatistics)
+	}
+
+	// Voxelizes and lights the volume, `changed` tells whether anything it depends on changed since
+	// the last update
+	fn update_volume(&mut self, camera: &Camera, changed: bool) {
+		let storage = self.volume_scene.storage();
+		let dense = storage == VolumeStorage::Dense;
+		// The fragment list always takes every primitive
+		let split = dense && self.static_split && !self.fragment_output;
+		if dense && !split {
+			self.clear_volume();
+			self.static_voxels = None;
+		}
+
+		let collect_statistics = self.collects_statistics();
+		if collect_statistics {
+			self.statistics_counters.clear();
+			if let Some(queries) = &self.pipeline_queries {
+				queries.begin();
+			}
+		}
+
+		let mut overlay = false;
+		match storage {
+			VolumeStorage::Dense if self.fragment_output => {
+				self.voxelize_into_fragment_list();
+			}
+			VolumeStorage::Dense if split => {
+				overlay = self.voxelize_split();
+				self.fragment_count = None;
+			}
+			VolumeStorage::Dense => {
+				let target = self.volume_scene.voxel_target();
+				self.voxelize_dense(&target, PrimitiveSet::All);
+				self.fragment_count = None;
+			}
+			VolumeStorage::Octree => self.update_octree(),
+			VolumeStorage::Clipmap => {
+				self.update_clipmap(camera, changed);
+				self.fragment_count = None;
+			}
+		}
+
+		if collect_statistics {
+			if let Some(queries) = &self.pipeline_queries {
+				queries.end();
+			}
+			if dense {
+				self.count_occupied_voxels();
+			}
+		}
+
+		if dense {
+			self.inject_light(overlay);
+
+			self.timer.begin("generate_mipmap");
+			self.volume_scene.generate_mipmap();
+			self.timer.end("generate_mipmap");
+		}
+	}
+
+	pub fn render(&mut self, camera: &Camera) {
+		self.reload_shaders();
+
+		self.timer.begin_frame();
+		self.timer.begin("frame");
+		// self.render_to_shadow_map();
+
+		// The counters are only filled while voxelizing
+		let collect_statistics = self.collects_statistics();
+		let state = self.volume_state();
+		self.light_buffer.upload(&self.lights);
+		let changed =
+			self.force_revoxelize || collect_statistics || self.volume_state.as_ref() != Some(&state);
+		self.volume_state = Some(state);
+
+		// The clipmap follows the camera, so it may have to voxelize even when nothing changed
+		let updated = changed || self.volume_scene.storage() == VolumeStorage::Clipmap;
+		if updated {
+			self.update_volume(camera, changed);
+		}
 
 		gl_set_viewport(0, 0, self.viewport_size.0, self.viewport_size.1);
 		// gl_set_clear_color(&[0.8, 0.75, 0.79, 1.0]);
@@ -433,32 +1203,65 @@ impl Renderer {
 		gl_set_depth_write(true);
 		gl_clear(true, true, true);
 
-		if self.rendering_mode != RenderingMode::Scene {
-			self.render_voxels(camera);
+		// The voxel views draw the dense textures
+		let dense = self.volume_scene.storage() == VolumeStorage::Dense;
+		if self.rendering_mode != RenderingMode::Scene && dense {
+			self.render_voxels(camera, updated);
 		}
 
 		gl_set_cull_face(CullFace::Back);
 		self.render_scene(camera);
 
+		self.timer.end("frame");
 		self.timer.end_frame();
 
+		if collect_statistics {
+			self.statistics = Some(read_statistics(
+				&self.statistics_counters,
+				self.pipeline_queries.as_ref(),
+			));
+		}
+
 		if self.show_bounds {
 			self.render_bounds(camera);
 		}
 	}
 
-	pub fn render_voxels(&self, camera: &Camera) {
+	// The dense texture of the rendering mode, at the mip of the voxel views
+	pub fn viewed_texture(&self) -> ViewedTexture {
+		let volume = &self.volume_scene;
+		let (texture, mips) = match self.rendering_mode {
+			RenderingMode::Scene | RenderingMode::Albedo => (volume.albedo_id(), 1),
+			RenderingMode::Normal => (volume.normal_id(), 1),
+			RenderingMode::Emission => (volume.emission_id(), 1),
+			RenderingMode::Radiance => (volume.radiance_id(), RADIANCE_MIPS),
+		};
+
+		ViewedTexture {
+			texture,
+			resolution: volume.resolution()[0] as usize,
+			mip: self.voxel_view_mip.min(mips - 1),
+			threshold: self.voxel_alpha_threshold,
+		}
+	}
+
+	// `volume_changed` tells whether the volume was voxelized or lit since the last frame
+	pub fn render_voxels(&mut self, camera: &Camera, volume_changed: bool) {
+		let viewed = self.viewed_texture();
+
+		self.timer.begin("compact_voxels");
+		self
+			.voxel_view
+			.compact(&self.voxel_compact_program, &viewed, volume_changed);
+		self.timer.end("compact_voxels");
+
 		gl_set_cull_face(CullFace::None);
 
 		self.volume_view_program.bind();
-
-		match self.rendering_mode {
-			RenderingMode::Scene => self.volume_scene.bind_texture_albedo(0),
-			RenderingMode::Albedo => self.volume_scene.bind_texture_albedo(0),
-			RenderingMode::Normal => self.volume_scene.bind_texture_normal(0),
-			RenderingMode::Emission => self.volume_scene.bind_texture_emission(0),
-			RenderingMode::Radiance => self.volume_scene.bind_texture_radiance(0),
-		};
+		VIEW_MIP.set(viewed.mip as i32);
+		unsafe {
+			gl::BindTextureUnit(ALBEDO_UNIT, viewed.texture);
+		}
 
 		let translation = glm::translation(
 			&(self.volume_scene.view_translation() - self.volume_scene.view_scaling() * 0.5),
@@ -476,10 +1279,49 @@ impl Renderer {
 			.get_uniform("resolution")
 			.set_1i(self.volume_scene.resolution()[0] as i32);
 
-		self.volume_scene.draw();
+		self.voxel_view.draw();
+	}
+
+	// Copies a plane of the viewed texture into a 2D texture for the UI, returns it with its size
+	pub fn voxel_slice(&mut self, axis: SliceAxis, index: usize) -> (u32, usize) {
+		let viewed = self.viewed_texture();
+		self
+			.voxel_view
+			.slice(&self.voxel_slice_program, &viewed, axis, index)
+	}
+
+	// The raw RGBA8 value of a voxel of the viewed texture, in voxels of its mip. Stalls
+	pub fn pick_voxel(&self, voxel: [usize; 3]) -> [u8; 4] {
+		let viewed = self.viewed_texture();
+		let mut texel = [0u8; 4];
+		unsafe {
+			gl::MemoryBarrier(gl::TEXTURE_UPDATE_BARRIER_BIT);
+			gl::GetTextureSubImage(
+				viewed.texture,
+				viewed.mip as i32,
+				voxel[0] as i32,
+				voxel[1] as i32,
+				voxel[2] as i32,
+				1,
+				1,
+				1,
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+				texel.len() as i32,
+				texel.as_mut_ptr() as *mut GLvoid,
+			);
+		}
+
+		texel
 	}
 
 	pub fn render_scene(&mut self, camera: &Camera) {
+		self.timer.begin("cull_lights");
+		self
+			.light_clusters
+			.cull(&self.light_cull_program, &self.light_buffer, camera);
+		self.timer.end("cull_lights");
+
 		self.timer.begin("render_scene");
 
 		let proj_view: [f32; 16] = camera.proj_view_raw();
@@ -492,7 +1334,45 @@ impl Renderer {
 			.get_uniform("u_width")
 			.set_1i(self.volume_scene.resolution()[0] as i32);
 
-		self.volume_scene.bind_texture_radiance(4);
+		let storage = self.volume_scene.storage();
+		program
+			.get_uniform("u_octree")
+			.set_1i((storage == VolumeStorage::Octree) as i32);
+		program
+			.get_uniform("u_clipmap")
+			.set_1i((storage == VolumeStorage::Clipmap) as i32);
+
+		match storage {
+			VolumeStorage::Dense => self.volume_scene.bind_texture_radiance(RADIANCE_MAP_UNIT),
+			VolumeStorage::Octree => {
+				let octree = self.volume_scene.octree().unwrap();
+				octree.bind_for_tracing(RADIANCE_BRICKS_MAP_UNIT);
+				program
+					.get_uniform("u_octree_depth")
+					.set_1i(octree.depth() as i32);
+				program
+					.get_uniform("u_brick_pool_width")
+					.set_1i(octree.brick_pool_width() as i32);
+			}
+			VolumeStorage::Clipmap => {
+				let clipmap = self.volume_scene.clipmap().unwrap();
+				clipmap.bind_for_tracing(CASCADE_RADIANCE_MAP_UNIT);
+
+				let count = clipmap.cascade_count();
+				let mut mins = Vec::with_capacity(3 * count);
+				let mut extents = Vec::with_capacity(3 * count);
+				for cascade in 0..count {
+					mins.extend_from_slice(clipmap.min_corner(cascade).as_slice());
+					extents.extend_from_slice(&[clipmap.extent(cascade); 3]);
+				}
+
+				program.get_uniform("u_cascade_count").set_1i(count as i32);
+				program.get_uniform("u_cascade_min").set_3fv(&mins[..]);
+				program
+					.get_uniform("u_cascade_extent")
+					.set_3fv(&extents[..]);
+			}
+		}
 
 		let position = *self.volume_scene.translation();
 		let scale = *self.volume_scene.scaling();
@@ -504,19 +1384,9 @@ impl Renderer {
 			.get_uniform("u_volume_scale")
 			.set_3f(1, &scale.into());
 
-		let (positions, colors) = lights_to_soa(&self.lights);
-
-		program
-			.get_uniform("light_position")
-			.set_3fv(&positions[..]);
-		self
-			.pbr_program
-			.get_uniform("light_color")
-			.set_3fv(&colors[..]);
-		self
-			.pbr_program
-			.get_uniform("num_lights")
-			.set_1i(self.lights.len() as i32);
+		self.light_buffer.bind();
+		self.light_clusters.bind(camera);
+		VIEWPORT_SIZE.set(&[self.viewport_size.0 as f32, self.viewport_size.1 as f32]);
 
 		self
 			.pbr_program
@@ -552,24 +1422,136 @@ impl Renderer {
 		self.viewport_size = size;
 	}
 
-	pub fn submit_mesh(&mut self, mesh: &Mesh) {
+	pub fn submit_mesh(&mut self, mesh: &Mesh, dynamic: bool) {
 		for primitive in mesh.primitives() {
 			let material = self.fetch_material(&primitive.material);
-			let gpu_primitive = GpuPrimitive::new(
-				&primitive,
-				&self.pbr_program,
-				material,
-				mesh.position,
-				mesh.scale,
-			);
+			let gpu_primitive =
+				GpuPrimitive::new(&primitive, material, mesh.position, mesh.scale, dynamic);
 			self.primitives.push(gpu_primitive);
 		}
+		self.static_voxels = None;
+		self.volume_state = None;
+	}
+
+	pub fn clear_scene(&mut self) {
+		self.primitives.clear();
+		self.static_voxels = None;
+		self.volume_state = None;
+	}
+
+	pub fn set_resolution(&mut self, resolution: usize) {
+		let storage = self.volume_scene.storage();
+		self.set_volume(storage, resolution);
+	}
+
+	pub fn set_storage(&mut self, storage: VolumeStorage) {
+		let resolution = self.volume_scene.resolution()[0] as usize;
+		self.set_volume(storage, resolution);
+	}
+
+	pub fn set_cascade_count(&mut self, count: usize) {
+		let count = if count < 1 || count > MAX_CASCADES {
+			println!(
+				"The clipmap takes between 1 and {} cascades, not {}",
+				MAX_CASCADES, count
+			);
+			count.max(1).min(MAX_CASCADES)
+		} else {
+			count
+		};
+
+		self.volume_scene.set_cascade_count(count);
+	}
+
+	// Changes both at once, so the volume is only reallocated once
+	pub fn set_volume(&mut self, storage: VolumeStorage, resolution: usize) {
+		let (storage, resolution) = supported_volume(&self.capabilities, storage, resolution);
+
+		self.volume_scene.set_storage(storage, resolution);
+		// A new volume has lost its static voxels
+		self.static_voxels = None;
+	}
+
+	// Build logs of the shaders that failed to hot reload
+	pub fn shader_errors(&self) -> Vec<(&'static str, &str)> {
+		match &self.shader_manager {
+			Some(shader_manager) => shader_manager
+				.errors()
+				.iter()
+				.map(|(name, log)| (*name, &log[..]))
+				.collect(),
+			None => Vec::new(),
+		}
+	}
+
+	pub fn capabilities(&self) -> &GlCapabilities {
+		&self.capabilities
+	}
+
+	pub fn conservative_mode(&self) -> ConservativeMode {
+		self.conservative_mode
+	}
+
+	pub fn supported_conservative_modes(&self) -> &[ConservativeMode] {
+		&self.supported_conservative_modes
+	}
+
+	// Falls back to the next best supported mode, returns the one actually in use
+	pub fn set_conservative_mode(&mut self, mode: ConservativeMode) -> ConservativeMode {
+		let supported = &self.supported_conservative_modes;
+		let chosen = std::iter::once(mode)
+			.chain(mode.fallbacks().iter().cloned())
+			.find(|candidate| supported.contains(candidate))
+			.unwrap_or(ConservativeMode::GeometryShader);
+
+		if chosen != mode {
+			println!(
+				"Conservative mode '{}' is not supported, falling back to '{}'",
+				mode.name(),
+				chosen.name()
+			);
+		}
+
+		self.conservative_mode = chosen;
+		chosen
+	}
+
+	// The voxelizer that runs: the chosen one, or the first supported one, trying the fragment
+	// voxelizer first as it needs the least
+	pub fn effective_voxelization_mode(&self) -> VoxelizationMode {
+		let fallbacks = &[
+			VoxelizationMode::FragmentOnly,
+			VoxelizationMode::Hybrid,
+			VoxelizationMode::Compute,
+		];
+		std::iter::once(self.voxelization_mode)
+			.chain(fallbacks.iter().cloned())
+			.find(|mode| self.capabilities.supports(mode.feature()))
+			.unwrap_or(self.voxelization_mode)
+	}
+
+	// Keeps the chosen mode, so it is used again on a GPU that supports it, but reports the fallback
+	// once here rather than on every voxelization
+	pub fn set_voxelization_mode(&mut self, mode: VoxelizationMode) {
+		self.voxelization_mode = mode;
+		let effective = self.effective_voxelization_mode();
+		if effective != mode {
+			println!(
+				"The {} voxelizer is not supported, falling back to the {} one",
+				mode.name(),
+				effective.name()
+			);
+		}
 	}
 
 	pub fn light(&mut self, index: usize) -> &mut Light {
 		&mut self.lights[index]
 	}
 
+	pub fn volume(&self) -> &Volume {
+		&self.volume_scene
+	}
+
 	pub fn volume_mut(&mut self) -> &mut Volume {
 		&mut self.volume_scene
 	}
@@ -580,16 +1562,48 @@ impl Renderer {
 
 	pub fn save_diagnostics(&self, scene_name: &str) {
 		let resolution = self.volume_scene.resolution();
-		let file_name = if self.nv_conservative {
-			format!(
-				"{}_{}_conservative_{:.2}.csv",
-				resolution[0], scene_name, self.cutoff
-			)
-		} else {
-			format!("{}_{}_{:.2}.csv", resolution[0], scene_name, self.cutoff)
+		let file_name = match self.conservative_mode {
+			ConservativeMode::None => format!("{}_{}_{:.2}.csv", resolution[0], scene_name, self.cutoff),
+			mode => format!(
+				"{}_{}_conservative_{}_{:.2}.csv",
+				resolution[0],
+				scene_name,
+				mode.name(),
+				self.cutoff
+			),
 		};
 
-		self.timer.save_file(&file_name).unwrap();
+		match self.save_timings(&file_name) {
+			Ok(()) => println!("Timings written to '{}'", file_name),
+			Err(e) => println!("Couldn't save the timings: {}", e),
+		}
+	}
+
+	pub fn fragment_count(&self) -> Option<usize> {
+		self.fragment_count
+	}
+
+	// Writes the fragments of the last voxelization into the fragment list to a CSV file
+	pub fn save_fragments<P: AsRef<Path>>(&self, path: P) -> Result<(), csv::Error> {
+		let count = self.fragment_count.unwrap_or(0);
+		self.fragment_list.save(count, path)
+	}
+
+	// Counters of the last frame rendered with `collect_statistics` set
+	pub fn statistics(&self) -> Option<&VoxelizationStatistics> {
+		self.statistics.as_ref()
+	}
+
+	pub fn timer(&self) -> &GlTimer {
+		&self.timer
+	}
+
+	pub fn timer_mut(&mut self) -> &mut GlTimer {
+		&mut self.timer
+	}
+
+	pub fn save_timings<P: AsRef<Path>>(&self, path: P) -> Result<(), csv::Error> {
+		self.timer.save_file(path)
 	}
 
 	fn fetch_material(&mut self, material: &Material) -> Rc<GpuMaterial> {
@@ -637,3 +1651,56 @@ impl Renderer {
 		}
 	}
 }
+
+// The storage and resolution the volume can be allocated with. The octree falls back to dense storage
+// when it isn't supported, and the octree and the clipmap round the resolution up to a power of two
+// they can hold
+fn supported_volume(
+	capabilities: &GlCapabilities,
+	storage: VolumeStorage,
+	resolution: usize,
+) -> (VolumeStorage, usize) {
+	let storage = if storage == VolumeStorage::Octree && !capabilities.supports(Feature::SparseOctree)
+	{
+		println!("The sparse voxel octree is not supported, using dense storage");
+		VolumeStorage::Dense
+	} else {
+		storage
+	};
+
+	let supported = match storage {
+		VolumeStorage::Dense => resolution,
+		VolumeStorage::Octree => resolution
+			.next_power_of_two()
+			.max(2)
+			.min(MAX_OCTREE_RESOLUTION),
+		VolumeStorage::Clipmap => resolution.next_power_of_two().max(MIN_CLIPMAP_RESOLUTION),
+	};
+	if supported != resolution {
+		println!(
+			"The {} storage doesn't take a resolution of {}, using {}",
+			storage.name(),
+			resolution,
+			supported
+		);
+	}
+
+	(storage, supported)
+}
+
+// Lights and volume placement read by voxel_lighting.glsl, `program` must be bound
+fn set_voxel_lighting_uniforms(
+	program: &GLProgram,
+	lights: &LightBuffer,
+	center: &glm::Vec3,
+	scale: &glm::Vec3,
+) {
+	lights.bind();
+
+	program
+		.get_uniform("u_volume_center")
+		.set_3f(1, &(*center).into());
+	program
+		.get_uniform("u_volume_scale")
+		.set_3f(1, &(*scale).into());
+}
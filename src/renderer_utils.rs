@@ -1,104 +1,458 @@
 use crate::gpu_model::{GpuMaterial, GpuPrimitive};
+use crate::scene::light::Light;
 use crate::scene::material::{Material, Texture};
+use crate::shader_layout::*;
+use crate::shader_sources::ShaderSources;
 use gl_helpers::*;
 use glsl_include::Context;
 use nalgebra_glm as glm;
-use std::fs;
+use std::ptr;
 
 ////////////////////
 // SHADER HELPERS //
-static VERTEX_EXPECT: &str = "Couldn't read the vertex shader :(";
-static GEOMETRY_EXPECT: &str = "Couldn't read the geometry shader :(";
-static FRAGMENT_EXPECT: &str = "Couldn't read the fragment shader :(";
-static COMPUTE_EXPECT: &str = "Couldn't read the compute shader :(";
-static SHARED_EXPECT: &str = "Couldn't read the shared glsl :(";
-static EXPAND_EXPECT: &str = "Something went wrong in the expansion :(";
-
-pub fn load_shared_glsl_context<'a>() -> (Context<'a>) {
-	let shared_src = fs::read_to_string("src/shaders/shared.glsl").expect(SHARED_EXPECT);
+#[derive(Copy, Clone, PartialEq)]
+pub enum ShaderStage {
+	Vertex,
+	Geometry,
+	Fragment,
+	Compute,
+}
 
-	let mut context = Context::new();
-	context.include("shared.glsl", &shared_src[..]);
+impl ShaderStage {
+	fn gl_kind(self) -> u32 {
+		match self {
+			ShaderStage::Vertex => gl::VERTEX_SHADER,
+			ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+			ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+			ShaderStage::Compute => gl::COMPUTE_SHADER,
+		}
+	}
 
-	context
+	fn name(self) -> &'static str {
+		match self {
+			ShaderStage::Vertex => "vertex",
+			ShaderStage::Geometry => "geometry",
+			ShaderStage::Fragment => "fragment",
+			ShaderStage::Compute => "compute",
+		}
+	}
 }
 
-pub fn load_pbr_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/pbr.vert").expect(VERTEX_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/pbr.frag").expect(FRAGMENT_EXPECT);
-
-	let context = load_shared_glsl_context();
-	let vs_src = context.expand(vs_src).expect(EXPAND_EXPECT);
-	let fs_src = context.expand(fs_src).expect(EXPAND_EXPECT);
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ProgramId {
+	Pbr,
+	Depth,
+	VoxelView,
+	VoxelCompact,
+	VoxelSlice,
+	VoxelResolve,
+	Voxelize,
+	Classify,
+	VoxelizeCompute,
+	Bounds,
+	RadianceInjection,
+	LightCull,
+	CountVoxels,
+	Mipmap,
+	OctreeFlag,
+	OctreeAllocate,
+	OctreeStore,
+	OctreeInject,
+	OctreeFilter,
+}
 
-	GLProgram::new(&vs_src[..], &fs_src[..])
+pub struct ProgramDescription {
+	pub id: ProgramId,
+	pub name: &'static str,
+	pub stages: &'static [(ShaderStage, &'static str)],
+	// Whether the sources go through the shared #include context
+	pub includes: bool,
+	pub layout: &'static ProgramLayout,
 }
 
-pub fn load_depth_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/depth_pass.vert").expect(VERTEX_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/depth_pass.frag").expect(FRAGMENT_EXPECT);
+pub static PBR_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Pbr,
+	name: "pbr",
+	stages: &[
+		(ShaderStage::Vertex, "pbr.vert"),
+		(ShaderStage::Fragment, "pbr.frag"),
+	],
+	includes: true,
+	layout: &PBR_LAYOUT,
+};
+
+pub static DEPTH_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Depth,
+	name: "depth",
+	stages: &[
+		(ShaderStage::Vertex, "depth_pass.vert"),
+		(ShaderStage::Fragment, "depth_pass.frag"),
+	],
+	includes: false,
+	layout: &NO_LAYOUT,
+};
+
+pub static VOXEL_VIEW_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::VoxelView,
+	name: "voxel_view",
+	stages: &[
+		(ShaderStage::Vertex, "voxel_view.vert"),
+		(ShaderStage::Fragment, "voxel_view.frag"),
+	],
+	includes: false,
+	layout: &VOXEL_VIEW_LAYOUT,
+};
+
+pub static VOXEL_COMPACT_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::VoxelCompact,
+	name: "voxel_compact",
+	stages: &[(ShaderStage::Compute, "voxel_compact.comp")],
+	includes: false,
+	layout: &VOXEL_COMPACT_LAYOUT,
+};
+
+pub static VOXEL_SLICE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::VoxelSlice,
+	name: "voxel_slice",
+	stages: &[(ShaderStage::Compute, "voxel_slice.comp")],
+	includes: false,
+	layout: &VOXEL_SLICE_LAYOUT,
+};
+
+pub static VOXEL_RESOLVE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::VoxelResolve,
+	name: "voxel_resolve",
+	stages: &[(ShaderStage::Compute, "voxel_resolve.comp")],
+	includes: true,
+	layout: &VOXEL_RESOLVE_LAYOUT,
+};
+
+pub static VOXELIZE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Voxelize,
+	name: "voxelize",
+	stages: &[
+		(ShaderStage::Vertex, "voxelize.vert"),
+		(ShaderStage::Geometry, "voxelize.geom"),
+		(ShaderStage::Fragment, "voxelize.frag"),
+	],
+	includes: true,
+	layout: &VOXELIZE_LAYOUT,
+};
+
+pub static CLASSIFY_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Classify,
+	name: "classify",
+	stages: &[
+		(ShaderStage::Vertex, "classify.vert"),
+		(ShaderStage::Geometry, "classify.geom"),
+		(ShaderStage::Fragment, "classify.frag"),
+	],
+	includes: true,
+	layout: &CLASSIFY_LAYOUT,
+};
+
+pub static VOXELIZE_COMPUTE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::VoxelizeCompute,
+	name: "voxelize_compute",
+	stages: &[(ShaderStage::Compute, "voxelize.comp")],
+	includes: true,
+	layout: &VOXELIZE_COMPUTE_LAYOUT,
+};
+
+pub static BOUNDS_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Bounds,
+	name: "bounds",
+	stages: &[
+		(ShaderStage::Vertex, "volume_bounds.vert"),
+		(ShaderStage::Fragment, "volume_bounds.frag"),
+	],
+	includes: false,
+	layout: &NO_LAYOUT,
+};
+
+pub static RADIANCE_INJECTION_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::RadianceInjection,
+	name: "radiance_injection",
+	stages: &[(ShaderStage::Compute, "radiance_injection.comp")],
+	includes: true,
+	layout: &RADIANCE_INJECTION_LAYOUT,
+};
+
+pub static LIGHT_CULL_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::LightCull,
+	name: "light_cull",
+	stages: &[(ShaderStage::Compute, "light_cull.comp")],
+	includes: true,
+	layout: &LIGHT_CULL_LAYOUT,
+};
+
+pub static COUNT_VOXELS_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::CountVoxels,
+	name: "count_voxels",
+	stages: &[(ShaderStage::Compute, "count_voxels.comp")],
+	includes: false,
+	layout: &COUNT_VOXELS_LAYOUT,
+};
+
+pub static MIPMAP_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::Mipmap,
+	name: "mipmap",
+	stages: &[(ShaderStage::Compute, "mipmap.comp")],
+	includes: false,
+	layout: &MIPMAP_LAYOUT,
+};
+
+pub static OCTREE_FLAG_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::OctreeFlag,
+	name: "octree_flag",
+	stages: &[(ShaderStage::Compute, "octree_flag.comp")],
+	includes: true,
+	layout: &OCTREE_FLAG_LAYOUT,
+};
+
+pub static OCTREE_ALLOCATE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::OctreeAllocate,
+	name: "octree_allocate",
+	stages: &[(ShaderStage::Compute, "octree_allocate.comp")],
+	includes: true,
+	layout: &OCTREE_ALLOCATE_LAYOUT,
+};
+
+pub static OCTREE_STORE_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::OctreeStore,
+	name: "octree_store",
+	stages: &[(ShaderStage::Compute, "octree_store.comp")],
+	includes: true,
+	layout: &OCTREE_STORE_LAYOUT,
+};
+
+pub static OCTREE_INJECT_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::OctreeInject,
+	name: "octree_inject",
+	stages: &[(ShaderStage::Compute, "octree_inject.comp")],
+	includes: true,
+	layout: &OCTREE_INJECT_LAYOUT,
+};
+
+pub static OCTREE_FILTER_PROGRAM: ProgramDescription = ProgramDescription {
+	id: ProgramId::OctreeFilter,
+	name: "octree_filter",
+	stages: &[(ShaderStage::Compute, "octree_filter.comp")],
+	includes: true,
+	layout: &OCTREE_FILTER_LAYOUT,
+};
+
+pub static ALL_PROGRAMS: [&ProgramDescription; 19] = [
+	&PBR_PROGRAM,
+	&DEPTH_PROGRAM,
+	&VOXEL_VIEW_PROGRAM,
+	&VOXEL_COMPACT_PROGRAM,
+	&VOXEL_SLICE_PROGRAM,
+	&VOXEL_RESOLVE_PROGRAM,
+	&VOXELIZE_PROGRAM,
+	&CLASSIFY_PROGRAM,
+	&VOXELIZE_COMPUTE_PROGRAM,
+	&BOUNDS_PROGRAM,
+	&RADIANCE_INJECTION_PROGRAM,
+	&LIGHT_CULL_PROGRAM,
+	&COUNT_VOXELS_PROGRAM,
+	&MIPMAP_PROGRAM,
+	&OCTREE_FLAG_PROGRAM,
+	&OCTREE_ALLOCATE_PROGRAM,
+	&OCTREE_STORE_PROGRAM,
+	&OCTREE_INJECT_PROGRAM,
+	&OCTREE_FILTER_PROGRAM,
+];
+
+pub fn load_shared_glsl_context<'a>(sources: &ShaderSources) -> Result<Context<'a>, String> {
+	let mut context = Context::new();
+	for file in ShaderSources::include_files() {
+		let source = sources.read(file)?;
+		context.include(file, &source[..]);
+	}
 
-	GLProgram::new(&vs_src[..], &fs_src[..])
+	Ok(context)
 }
 
-pub fn load_voxel_view_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/voxel_view.vert").expect(VERTEX_EXPECT);
-	let gs_src = fs::read_to_string("src/shaders/voxel_view.geom").expect(GEOMETRY_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/voxel_view.frag").expect(FRAGMENT_EXPECT);
+// Reads every stage and expands its includes, in the order of the description
+pub fn expand_program(
+	sources: &ShaderSources,
+	description: &ProgramDescription,
+) -> Result<Vec<(ShaderStage, String)>, String> {
+	let context = if description.includes {
+		Some(load_shared_glsl_context(sources)?)
+	} else {
+		None
+	};
+
+	let mut stages = Vec::new();
+	for (stage, file) in description.stages {
+		let source = sources.read(file)?;
+		let source = match &context {
+			Some(context) => context
+				.expand(source)
+				.map_err(|e| format!("Couldn't expand the includes of '{}': {:?}", file, e))?,
+			None => source,
+		};
+		stages.push((*stage, source));
+	}
 
-	GLProgram::new_gs(&vs_src[..], &gs_src[..], &fs_src[..])
+	Ok(stages)
 }
 
-pub fn load_voxelize_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/voxelize.vert").expect(VERTEX_EXPECT);
-	let gs_src = fs::read_to_string("src/shaders/voxelize.geom").expect(GEOMETRY_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/voxelize.frag").expect(FRAGMENT_EXPECT);
+// Reads, expands and builds every stage, the error holds the GLSL log
+pub fn build_program(
+	sources: &ShaderSources,
+	description: &ProgramDescription,
+) -> Result<GLProgram, String> {
+	let stages = expand_program(sources, description)?;
+
+	let source = |stage: ShaderStage| {
+		stages
+			.iter()
+			.find(|(s, _)| *s == stage)
+			.map(|(_, source)| &source[..])
+	};
 
-	let context = load_shared_glsl_context();
-	let vs_src = context.expand(vs_src).expect(EXPAND_EXPECT);
-	let gs_src = context.expand(gs_src).expect(EXPAND_EXPECT);
-	let fs_src = context.expand(fs_src).expect(EXPAND_EXPECT);
+	use ShaderStage::*;
+	let program = match (
+		source(Vertex),
+		source(Geometry),
+		source(Fragment),
+		source(Compute),
+	) {
+		(Some(vs), None, Some(fs), None) => GLProgram::new(vs, fs),
+		(Some(vs), Some(gs), Some(fs), None) => GLProgram::new_gs(vs, gs, fs),
+		(None, None, None, Some(cs)) => GLProgram::new_comp(cs),
+		_ => {
+			return Err(format!(
+				"Program '{}' has an unsupported combination of stages",
+				description.name
+			))
+		}
+	};
 
-	GLProgram::new_gs(&vs_src[..], &gs_src[..], &fs_src[..])
+	check_program(description, &program)?;
+	Ok(program)
 }
 
-pub fn load_classify_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/classify.vert").expect(VERTEX_EXPECT);
-	let gs_src = fs::read_to_string("src/shaders/classify.geom").expect(GEOMETRY_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/classify.frag").expect(FRAGMENT_EXPECT);
+pub fn load_program(sources: &ShaderSources, description: &ProgramDescription) -> GLProgram {
+	build_program(sources, description).unwrap_or_else(|log| panic!("{}", log))
+}
 
-	let context = load_shared_glsl_context();
-	let vs_src = context.expand(vs_src).expect(EXPAND_EXPECT);
-	let gs_src = context.expand(gs_src).expect(EXPAND_EXPECT);
-	let fs_src = context.expand(fs_src).expect(EXPAND_EXPECT);
+// GLProgram doesn't report a broken shader, so the program it built is checked before it replaces
+// a working one: the compile status of its stages, the link status and the layout the renderer
+// binds with
+fn check_program(description: &ProgramDescription, program: &GLProgram) -> Result<(), String> {
+	let name = description.name;
+	let handle = program_handle(program);
+	let mut errors = Vec::new();
+
+	for shader in attached_shaders(handle) {
+		let mut status = 0;
+		let mut kind = 0;
+		unsafe {
+			gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+			gl::GetShaderiv(shader, gl::SHADER_TYPE, &mut kind);
+		}
+		if status == gl::TRUE as i32 {
+			continue;
+		}
 
-	GLProgram::new_gs(&vs_src[..], &gs_src[..], &fs_src[..])
+		let stage = description
+			.stages
+			.iter()
+			.find(|(stage, _)| stage.gl_kind() == kind as u32)
+			.map_or("unknown", |(stage, _)| stage.name());
+		errors.push(format!(
+			"{} shader of '{}':\n{}",
+			stage,
+			name,
+			shader_log(shader)
+		));
+	}
+
+	if errors.is_empty() {
+		if let Err(log) = check_link(handle, description.layout) {
+			errors.push(format!("Linking '{}':\n{}", name, log));
+		}
+	}
+
+	if errors.is_empty() {
+		Ok(())
+	} else {
+		Err(errors.join("\n"))
+	}
 }
 
-pub fn load_bounds_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/volume_bounds.vert").expect(VERTEX_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/volume_bounds.frag").expect(FRAGMENT_EXPECT);
+// GLProgram doesn't expose its handle, binding it is the way to get it
+fn program_handle(program: &GLProgram) -> u32 {
+	let mut handle = 0;
+	program.bind();
+	unsafe {
+		gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut handle);
+		gl::UseProgram(0);
+	}
 
-	GLProgram::new(&vs_src[..], &fs_src[..])
+	handle as u32
 }
 
-pub fn load_clear_program() -> GLProgram {
-	let vs_src = fs::read_to_string("src/shaders/clear_volume.vert").expect(VERTEX_EXPECT);
-	let fs_src = fs::read_to_string("src/shaders/empty.frag").expect(FRAGMENT_EXPECT);
+fn attached_shaders(program: u32) -> Vec<u32> {
+	let mut count = 0;
+	unsafe {
+		gl::GetProgramiv(program, gl::ATTACHED_SHADERS, &mut count);
+	}
 
-	GLProgram::new(&vs_src[..], &fs_src[..])
+	let mut shaders = vec![0u32; count as usize];
+	unsafe {
+		gl::GetAttachedShaders(program, count, ptr::null_mut(), shaders.as_mut_ptr());
+	}
+
+	shaders
 }
 
-pub fn load_radiance_injection_program() -> GLProgram {
-	let cs_src = fs::read_to_string("src/shaders/radiance_injection.comp").expect(COMPUTE_EXPECT);
+fn shader_log(shader: u32) -> String {
+	let mut length = 0;
+	unsafe {
+		gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+	}
 
-	GLProgram::new_comp(&cs_src[..])
+	let mut log = vec![0u8; length.max(1) as usize];
+	unsafe {
+		gl::GetShaderInfoLog(shader, length, ptr::null_mut(), log.as_mut_ptr() as *mut _);
+	}
+
+	info_log_to_string(log)
 }
 
-pub fn load_mipmap_program() -> GLProgram {
-	let cs_src = fs::read_to_string("src/shaders/mipmap.comp").expect(COMPUTE_EXPECT);
+// Also checks the linked program against the layout the renderer binds with
+fn check_link(program: u32, layout: &ProgramLayout) -> Result<(), String> {
+	let mut status = 0;
+	unsafe {
+		gl::GetProgramiv(program, gl::LINK_STATUS, &mut status);
+	}
+	if status == gl::TRUE as i32 {
+		return validate_layout(program, layout);
+	}
+
+	let mut length = 0;
+	unsafe {
+		gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+	}
+
+	let mut log = vec![0u8; length.max(1) as usize];
+	unsafe {
+		gl::GetProgramInfoLog(program, length, ptr::null_mut(), log.as_mut_ptr() as *mut _);
+	}
+
+	Err(info_log_to_string(log))
+}
 
-	GLProgram::new_comp(&cs_src[..])
+fn info_log_to_string(log: Vec<u8>) -> String {
+	String::from_utf8_lossy(&log)
+		.trim_end_matches('\0')
+		.trim_end()
+		.to_owned()
 }
 
 //////////////////////
@@ -137,51 +491,12 @@ pub fn load_depth_texture() -> GLTexture {
 
 ///////////////////
 // LIGHT HELPERS //
-pub struct Light {
-	pub position: glm::Vec3,
-	pub color: glm::Vec3,
-	pub intensity: f32,
-}
-
-pub fn lights_to_soa(lights: &Vec<Light>) -> (Vec<f32>, Vec<f32>) {
-	let positions: Vec<f32> = lights
-		.iter()
-		.map(|l| l.position.into_iter())
-		.flatten()
-		.cloned()
-		.collect();
-
-	let colors_unflattened: Vec<glm::Vec3> = lights.iter().map(|l| (l.color * l.intensity)).collect();
-
-	let colors: Vec<f32> = colors_unflattened
-		.iter()
-		.map(|c| c)
-		.flatten()
-		.cloned()
-		.collect();
-
-	(positions, colors)
-}
-
 pub fn load_lights() -> Vec<Light> {
-	let mut lights = Vec::new();
-	lights.push(Light {
-		position: glm::vec3(-2.5, 9.0, 0.0),
-		color: glm::vec3(0.815, 0.0, 0.333),
-		intensity: 1.0,
-	});
-	lights.push(Light {
-		position: glm::vec3(2.5, 9.0, 0.0),
-		color: glm::vec3(0.0, 0.815, 0.333),
-		intensity: 1.0,
-	});
-	lights.push(Light {
-		position: glm::vec3(0.0, 9.0, -2.5),
-		color: glm::vec3(0.0, 0.666, 1.0),
-		intensity: 1.0,
-	});
-
-	lights
+	vec![
+		Light::point(glm::vec3(-2.5, 9.0, 0.0), glm::vec3(0.815, 0.0, 0.333), 1.0),
+		Light::point(glm::vec3(2.5, 9.0, 0.0), glm::vec3(0.0, 0.815, 0.333), 1.0),
+		Light::point(glm::vec3(0.0, 9.0, -2.5), glm::vec3(0.0, 0.666, 1.0), 1.0),
+	]
 }
 
 pub fn light_matrix(light: &Light) -> [f32; 16] {
@@ -203,18 +518,27 @@ pub fn light_matrix(light: &Light) -> [f32; 16] {
 use crate::textures::Volume;
 
 pub fn voxelization_pv(volume: &Volume) -> [f32; 16] {
-	let half_width = volume.scaling().x as f32 / 2.0;
-	let half_height = volume.scaling().y as f32 / 2.0;
-	let depth = volume.scaling().z;
+	box_pv(
+		volume.translation(),
+		volume.scaling(),
+		&glm::vec3(0.1, 0.1, 0.01),
+	)
+}
+
+// Looks down -z at a box, `margin` widens the far sides of the projection
+pub fn box_pv(center: &glm::Vec3, scale: &glm::Vec3, margin: &glm::Vec3) -> [f32; 16] {
+	let half_width = scale.x / 2.0;
+	let half_height = scale.y / 2.0;
+	let depth = scale.z;
 	let proj = glm::ortho_rh(
 		-half_width,
-		half_width + 0.1,
+		half_width + margin.x,
 		-half_height,
-		half_height + 0.1,
-		depth + 0.01,
+		half_height + margin.y,
+		depth + margin.z,
 		0.0,
 	);
-	let position = volume.translation() + glm::vec3(0.0, 0.0, volume.scaling()[2] * 0.5);
+	let position = center + glm::vec3(0.0, 0.0, scale.z * 0.5);
 	let view = glm::look_at_rh(
 		&position,
 		&(position + glm::vec3(0.0, 0.0, -1.0)),
@@ -229,6 +553,58 @@ pub fn voxelization_pv(volume: &Volume) -> [f32; 16] {
 	pv
 }
 
+// The voxels a voxelization pass writes, see voxel_region.glsl
+#[derive(Clone, Copy)]
+pub struct VoxelRegion {
+	pub offset: [i32; 3],
+	pub min: [i32; 3],
+	pub max: [i32; 3],
+}
+
+impl VoxelRegion {
+	pub fn whole(resolution: usize) -> VoxelRegion {
+		VoxelRegion {
+			offset: [0; 3],
+			min: [0; 3],
+			max: [resolution as i32; 3],
+		}
+	}
+
+	// Expects the voxelization program to be bound
+	pub fn set_uniforms(&self) {
+		VOXEL_OFFSET.set(&self.offset);
+		REGION_MIN.set(&self.min);
+		REGION_MAX.set(&self.max);
+	}
+}
+
+// Where a voxelization pass writes: the attribute textures of a volume, bound as images, and the
+// projection that maps the volume to its voxels
+#[derive(Clone, Copy)]
+pub struct VoxelTarget {
+	pub albedo: u32,
+	pub normal: u32,
+	pub emission: u32,
+	pub resolution: [i32; 3],
+	pub pv: [f32; 16],
+	pub region: VoxelRegion,
+}
+
+impl VoxelTarget {
+	pub fn bind_images(&self) {
+		let images = [
+			(ALBEDO_UNIT, self.albedo),
+			(NORMAL_UNIT, self.normal),
+			(EMISSION_UNIT, self.emission),
+		];
+		for (unit, texture) in &images {
+			unsafe {
+				gl::BindImageTexture(*unit, *texture, 0, gl::TRUE, 0, gl::READ_WRITE, gl::RGBA8);
+			}
+		}
+	}
+}
+
 pub struct AtomicCounter {
 	handle: u32,
 }
@@ -428,3 +804,34 @@ impl IndicesBuffer {
 		}
 	}
 }
+
+// A framebuffer without attachments, so voxelizing at a higher resolution than the window isn't
+// clipped to the window's size
+pub struct EmptyFramebuffer {
+	handle: u32,
+}
+
+impl EmptyFramebuffer {
+	pub fn new() -> EmptyFramebuffer {
+		let mut handle = 0;
+		unsafe {
+			gl::CreateFramebuffers(1, &mut handle);
+		}
+
+		EmptyFramebuffer { handle }
+	}
+
+	pub fn bind(&self, size: usize) {
+		unsafe {
+			gl::NamedFramebufferParameteri(self.handle, gl::FRAMEBUFFER_DEFAULT_WIDTH, size as i32);
+			gl::NamedFramebufferParameteri(self.handle, gl::FRAMEBUFFER_DEFAULT_HEIGHT, size as i32);
+			gl::BindFramebuffer(gl::FRAMEBUFFER, self.handle);
+		}
+	}
+
+	pub fn unbind(&self) {
+		unsafe {
+			gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+		}
+	}
+}
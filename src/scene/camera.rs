@@ -8,6 +8,8 @@ pub struct Camera {
 
 impl Camera {
 	const UP: [f32; 3] = [0.0, 1.0, 0.0];
+	pub const NEAR: f32 = 0.01;
+	pub const FAR: f32 = 600.0;
 
 	pub const fn new(position: glm::Vec3, yaw: f32, pitch: f32) -> Camera {
 		Camera {
@@ -18,7 +20,7 @@ impl Camera {
 	}
 
 	pub fn projection(&self) -> glm::Mat4 {
-		glm::perspective_rh_zo(16.0 / 9.0, f32::to_radians(80.0), 0.01, 600.0)
+		glm::perspective_rh_zo(16.0 / 9.0, f32::to_radians(80.0), Self::NEAR, Self::FAR)
 	}
 
 	pub fn view(&self) -> glm::Mat4 {
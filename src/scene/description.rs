@@ -0,0 +1,200 @@
+use crate::renderer::{ConservativeMode, Renderer, VoxelizationMode};
+use crate::scene::camera::Camera;
+use crate::scene::light::*;
+use crate::scene::model::{Mesh, Resources};
+use crate::textures::VolumeStorage;
+use failure::{format_err, Error};
+use nalgebra_glm as glm;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize)]
+pub struct SceneDescription {
+	pub name: String,
+	pub meshes: Vec<MeshDescription>,
+	#[serde(default)]
+	pub lights: Vec<LightDescription>,
+	pub volume: VolumeDescription,
+	pub camera: CameraDescription,
+	#[serde(default)]
+	pub voxelization: VoxelizationDescription,
+}
+
+#[derive(Deserialize)]
+pub struct MeshDescription {
+	pub path: String,
+	#[serde(default = "zero")]
+	pub position: [f32; 3],
+	#[serde(default = "one")]
+	pub scale: [f32; 3],
+	// Moving meshes are voxelized every frame, the others only once
+	#[serde(default)]
+	pub dynamic: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LightDescription {
+	pub kind: LightKind,
+	pub position: [f32; 3],
+	pub direction: [f32; 3],
+	pub color: [f32; 3],
+	pub intensity: f32,
+	pub range: f32,
+	pub inner_angle: f32,
+	pub outer_angle: f32,
+	pub size: [f32; 2],
+}
+
+impl Default for LightDescription {
+	fn default() -> LightDescription {
+		LightDescription {
+			kind: LightKind::Point,
+			position: [0.0, 0.0, 0.0],
+			direction: [0.0, -1.0, 0.0],
+			color: [1.0, 1.0, 1.0],
+			intensity: 1.0,
+			range: DEFAULT_RANGE,
+			inner_angle: DEFAULT_INNER_ANGLE,
+			outer_angle: DEFAULT_OUTER_ANGLE,
+			size: [1.0, 1.0],
+		}
+	}
+}
+
+#[derive(Deserialize)]
+pub struct VolumeDescription {
+	pub translation: [f32; 3],
+	pub scaling: [f32; 3],
+	pub view_translation: [f32; 3],
+	pub view_scaling: [f32; 3],
+	#[serde(default)]
+	pub resolution: Option<usize>,
+	#[serde(default)]
+	pub storage: Option<VolumeStorage>,
+}
+
+#[derive(Deserialize)]
+pub struct CameraDescription {
+	pub position: [f32; 3],
+	#[serde(default)]
+	pub yaw: f32,
+	#[serde(default)]
+	pub pitch: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct VoxelizationDescription {
+	pub mode: VoxelizationMode,
+	pub conservative: ConservativeMode,
+	pub cutoff: f32,
+}
+
+impl Default for VoxelizationDescription {
+	fn default() -> VoxelizationDescription {
+		VoxelizationDescription {
+			mode: VoxelizationMode::Hybrid,
+			conservative: ConservativeMode::Nv,
+			cutoff: 1.0,
+		}
+	}
+}
+
+impl SceneDescription {
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneDescription, Error> {
+		let path = path.as_ref();
+		let source = fs::read_to_string(path)
+			.map_err(|e| format_err!("Couldn't read scene '{}': {}", path.display(), e))?;
+
+		let description: SceneDescription = ron::de::from_str(&source)
+			.map_err(|e| format_err!("Couldn't parse scene '{}': {}", path.display(), e))?;
+
+		for mesh in &description.meshes {
+			if !Path::new(&mesh.path).is_file() {
+				return Err(format_err!(
+					"Scene '{}' references a missing mesh '{}'",
+					path.display(),
+					mesh.path
+				));
+			}
+		}
+
+		Ok(description)
+	}
+}
+
+pub fn load_scene(
+	renderer: &mut Renderer,
+	camera: &mut Camera,
+	path: &str,
+) -> Result<String, Error> {
+	use glm::make_vec3;
+
+	let description = SceneDescription::load(path)?;
+
+	renderer.clear_scene();
+
+	let mut resources = Resources::new();
+	for mesh in &description.meshes {
+		renderer.submit_mesh(
+			&Mesh::new(
+				&mesh.path,
+				make_vec3(&mesh.position),
+				make_vec3(&mesh.scale),
+				&mut resources,
+			),
+			mesh.dynamic,
+		);
+	}
+
+	renderer.lights = description
+		.lights
+		.iter()
+		.map(|light| Light {
+			kind: light.kind,
+			position: make_vec3(&light.position),
+			direction: make_vec3(&light.direction),
+			color: make_vec3(&light.color),
+			intensity: light.intensity,
+			range: light.range,
+			inner_angle: light.inner_angle,
+			outer_angle: light.outer_angle,
+			size: glm::make_vec2(&light.size),
+		})
+		.collect();
+
+	let volume = &description.volume;
+	let storage = volume
+		.storage
+		.unwrap_or_else(|| renderer.volume().storage());
+	let resolution = volume
+		.resolution
+		.unwrap_or_else(|| renderer.volume().resolution()[0] as usize);
+	renderer.set_volume(storage, resolution);
+
+	let volume = renderer.volume_mut();
+	*volume.translation_mut() = make_vec3(&description.volume.translation);
+	*volume.scaling_mut() = make_vec3(&description.volume.scaling);
+	*volume.view_translation_mut() = make_vec3(&description.volume.view_translation);
+	*volume.view_scaling_mut() = make_vec3(&description.volume.view_scaling);
+
+	renderer.set_voxelization_mode(description.voxelization.mode);
+	renderer.set_conservative_mode(description.voxelization.conservative);
+	renderer.cutoff = description.voxelization.cutoff;
+
+	camera.position = make_vec3(&description.camera.position);
+	camera.yaw = description.camera.yaw;
+	camera.pitch = description.camera.pitch;
+
+	Ok(description.name)
+}
+
+fn zero() -> [f32; 3] {
+	[0.0, 0.0, 0.0]
+}
+
+fn one() -> [f32; 3] {
+	[1.0, 1.0, 1.0]
+}
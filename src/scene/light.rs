@@ -1 +1,111 @@
+use nalgebra_glm as glm;
+use serde::Deserialize;
 
+// Past it the point, spot and area lights contribute nothing
+pub const DEFAULT_RANGE: f32 = 20.0;
+// Half angles of the spot cones in degrees
+pub const DEFAULT_INNER_ANGLE: f32 = 20.0;
+pub const DEFAULT_OUTER_ANGLE: f32 = 30.0;
+
+// Matches the LIGHT_* constants of lights.glsl
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum LightKind {
+	Point,
+	// Infinitely far away, only its direction matters, like the sun
+	Directional,
+	Spot,
+	// A one sided rectangle facing its direction
+	Rectangle,
+	Sphere,
+}
+
+impl LightKind {
+	pub const ALL: [LightKind; 5] = [
+		LightKind::Point,
+		LightKind::Directional,
+		LightKind::Spot,
+		LightKind::Rectangle,
+		LightKind::Sphere,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			LightKind::Point => "point",
+			LightKind::Directional => "directional",
+			LightKind::Spot => "spot",
+			LightKind::Rectangle => "rectangle",
+			LightKind::Sphere => "sphere",
+		}
+	}
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Light {
+	pub kind: LightKind,
+	pub position: glm::Vec3,
+	// Where directional, spot and rectangle lights point
+	pub direction: glm::Vec3,
+	pub color: glm::Vec3,
+	pub intensity: f32,
+	pub range: f32,
+	// Full intensity inside the inner half angle, none past the outer one
+	pub inner_angle: f32,
+	pub outer_angle: f32,
+	// Width and height of rectangle lights, the radius of sphere lights in x
+	pub size: glm::Vec2,
+}
+
+impl Light {
+	pub fn point(position: glm::Vec3, color: glm::Vec3, intensity: f32) -> Light {
+		Light {
+			kind: LightKind::Point,
+			position,
+			direction: glm::vec3(0.0, -1.0, 0.0),
+			color,
+			intensity,
+			range: DEFAULT_RANGE,
+			inner_angle: DEFAULT_INNER_ANGLE,
+			outer_angle: DEFAULT_OUTER_ANGLE,
+			size: glm::vec2(1.0, 1.0),
+		}
+	}
+
+	// The cosines of the spot cone half angles, the inner one first. The outer cone is kept a bit
+	// wider so the edge of the spot stays smooth
+	pub fn cone_cosines(&self) -> (f32, f32) {
+		let outer = self.outer_angle.max(self.inner_angle + 0.1);
+		(
+			self.inner_angle.to_radians().cos(),
+			outer.to_radians().cos(),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn spot(inner_angle: f32, outer_angle: f32) -> Light {
+		let mut light = Light::point(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 1.0), 1.0);
+		light.kind = LightKind::Spot;
+		light.inner_angle = inner_angle;
+		light.outer_angle = outer_angle;
+		light
+	}
+
+	#[test]
+	fn cone_cosines_of_the_half_angles() {
+		let (inner, outer) = spot(0.0, 60.0).cone_cosines();
+		assert!((inner - 1.0).abs() < 1e-6);
+		assert!((outer - 0.5).abs() < 1e-6);
+	}
+
+	#[test]
+	fn the_outer_cone_is_wider_than_the_inner_one() {
+		for &(inner_angle, outer_angle) in &[(30.0, 30.0), (45.0, 10.0)] {
+			let (inner, outer) = spot(inner_angle, outer_angle).cone_cosines();
+			assert!(outer < inner, "{} {}", inner_angle, outer_angle);
+			assert!((outer - (inner_angle + 0.1f32).to_radians().cos()).abs() < 1e-6);
+		}
+	}
+}
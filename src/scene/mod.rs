@@ -1,4 +1,6 @@
 pub mod camera;
+pub mod description;
+pub mod light;
 pub mod material;
 pub mod model;
 
@@ -0,0 +1,644 @@
+use gl;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use BindingKind::*;
+
+// A uniform with an explicit location, it can only be set with the type it was declared with
+pub struct Uniform<T> {
+	pub name: &'static str,
+	pub location: i32,
+	gl_type: u32,
+	value: PhantomData<T>,
+}
+
+impl<T> Uniform<T> {
+	pub const fn slot(&self) -> UniformSlot {
+		UniformSlot {
+			name: self.name,
+			location: self.location,
+			gl_type: self.gl_type,
+		}
+	}
+}
+
+impl Uniform<bool> {
+	pub const fn boolean(name: &'static str, location: i32) -> Uniform<bool> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::BOOL,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: bool) {
+		unsafe {
+			gl::Uniform1i(self.location, value as i32);
+		}
+	}
+}
+
+impl Uniform<f32> {
+	pub const fn float(name: &'static str, location: i32) -> Uniform<f32> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::FLOAT,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: f32) {
+		unsafe {
+			gl::Uniform1f(self.location, value);
+		}
+	}
+}
+
+impl Uniform<i32> {
+	pub const fn int(name: &'static str, location: i32) -> Uniform<i32> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::INT,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: i32) {
+		unsafe {
+			gl::Uniform1i(self.location, value);
+		}
+	}
+}
+
+impl Uniform<[f32; 2]> {
+	pub const fn vec2(name: &'static str, location: i32) -> Uniform<[f32; 2]> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::FLOAT_VEC2,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: &[f32; 2]) {
+		unsafe {
+			gl::Uniform2fv(self.location, 1, value.as_ptr());
+		}
+	}
+}
+
+impl Uniform<[i32; 3]> {
+	pub const fn ivec3(name: &'static str, location: i32) -> Uniform<[i32; 3]> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::INT_VEC3,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: &[i32; 3]) {
+		unsafe {
+			gl::Uniform3iv(self.location, 1, value.as_ptr());
+		}
+	}
+}
+
+impl Uniform<[f32; 16]> {
+	pub const fn mat4(name: &'static str, location: i32) -> Uniform<[f32; 16]> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::FLOAT_MAT4,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: &[f32; 16]) {
+		unsafe {
+			gl::UniformMatrix4fv(self.location, 1, gl::FALSE, value.as_ptr());
+		}
+	}
+}
+
+impl Uniform<u32> {
+	pub const fn uint(name: &'static str, location: i32) -> Uniform<u32> {
+		Uniform {
+			name,
+			location,
+			gl_type: gl::UNSIGNED_INT,
+			value: PhantomData,
+		}
+	}
+
+	pub fn set(&self, value: u32) {
+		unsafe {
+			gl::Uniform1ui(self.location, value);
+		}
+	}
+}
+
+pub struct UniformSlot {
+	pub name: &'static str,
+	pub location: i32,
+	pub gl_type: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BindingKind {
+	Sampler,
+	Image,
+	AtomicCounter,
+	StorageBlock,
+}
+
+pub struct Binding {
+	pub name: &'static str,
+	pub kind: BindingKind,
+	pub unit: u32,
+}
+
+const fn binding(name: &'static str, kind: BindingKind, unit: u32) -> Binding {
+	Binding { name, kind, unit }
+}
+
+// Everything a program expects to be set by location or bound to a fixed unit
+pub struct ProgramLayout {
+	pub uniforms: &'static [UniformSlot],
+	pub bindings: &'static [Binding],
+}
+
+// Uniforms shared by the voxelization programs
+pub const RESOLUTION: Uniform<[i32; 3]> = Uniform::ivec3("u_resolution", 0);
+pub const PV: Uniform<[f32; 16]> = Uniform::mat4("pv", 1);
+pub const EXPAND_TRIANGLES: Uniform<bool> = Uniform::boolean("u_expand_triangle", 2);
+pub const CUTOFF: Uniform<f32> = Uniform::float("u_cutoff", 3);
+pub const COLLECT_STATISTICS: Uniform<bool> = Uniform::boolean("u_collect_statistics", 4);
+pub const STORE_FRAGMENTS: Uniform<bool> = Uniform::boolean("u_store_fragments", 5);
+// Whether the voxelizers add to the sums of voxel_sums.glsl instead of averaging into the textures
+pub const ACCUMULATE_SUMS: Uniform<bool> = Uniform::boolean("u_accumulate_sums", 21);
+// Whether the voxelizers perturb the normals with the normal map of the primitive
+pub const NORMAL_MAPPING: Uniform<bool> = Uniform::boolean("u_normal_mapping", 22);
+// Where the voxelizers write, see voxel_region.glsl
+pub const VOXEL_OFFSET: Uniform<[i32; 3]> = Uniform::ivec3("u_voxel_offset", 13);
+pub const REGION_MIN: Uniform<[i32; 3]> = Uniform::ivec3("u_region_min", 14);
+pub const REGION_MAX: Uniform<[i32; 3]> = Uniform::ivec3("u_region_max", 15);
+pub const CHILD_MIP_DIMENSION: Uniform<u32> = Uniform::uint("child_mip_dimension", 0);
+// Whether the radiance injection merges the overlay of the dynamic primitives
+pub const DYNAMIC_OVERLAY: Uniform<bool> = Uniform::boolean("u_dynamic_overlay", 16);
+// What the voxel views and slices show
+pub const VIEW_MIP: Uniform<i32> = Uniform::int("u_mip", 17);
+pub const ALPHA_THRESHOLD: Uniform<f32> = Uniform::float("u_alpha_threshold", 18);
+pub const SLICE_AXIS: Uniform<i32> = Uniform::int("u_slice_axis", 19);
+pub const SLICE_INDEX: Uniform<i32> = Uniform::int("u_slice_index", 20);
+// The light buffer of lights.glsl and the clusters of light_clusters.glsl
+pub const LIGHT_COUNT: Uniform<u32> = Uniform::uint("u_light_count", 25);
+pub const CLUSTER_DEPTH: Uniform<[f32; 2]> = Uniform::vec2("u_cluster_depth", 23);
+pub const VIEWPORT_SIZE: Uniform<[f32; 2]> = Uniform::vec2("u_viewport_size", 24);
+pub const VIEW: Uniform<[f32; 16]> = Uniform::mat4("u_view", 26);
+pub const INVERSE_PROJECTION: Uniform<[f32; 16]> = Uniform::mat4("u_inverse_projection", 27);
+
+// Uniforms of the octree passes
+pub const OCTREE_LEVEL: Uniform<i32> = Uniform::int("u_level", 6);
+pub const NODE_OFFSET: Uniform<u32> = Uniform::uint("u_node_offset", 8);
+pub const NODE_COUNT: Uniform<u32> = Uniform::uint("u_node_count", 9);
+pub const NODE_CAPACITY: Uniform<u32> = Uniform::uint("u_node_capacity", 10);
+pub const BRICK_POOL_WIDTH: Uniform<i32> = Uniform::int("u_brick_pool_width", 11);
+pub const RESOLVE_COVERAGE: Uniform<bool> = Uniform::boolean("u_resolve", 12);
+
+// The volume textures use the same unit whether they are bound as images or samplers
+pub const ALBEDO_UNIT: u32 = 0;
+pub const NORMAL_UNIT: u32 = 1;
+pub const EMISSION_UNIT: u32 = 2;
+pub const RADIANCE_UNIT: u32 = 3;
+pub const OVERLAY_ALBEDO_UNIT: u32 = 4;
+pub const OVERLAY_NORMAL_UNIT: u32 = 5;
+pub const OVERLAY_EMISSION_UNIT: u32 = 6;
+// The 2D image a slice of the volume is copied into
+pub const SLICE_UNIT: u32 = 1;
+// Written by the hybrid classifier for the indirect draw of large triangles
+pub const LARGE_INDICES_UNIT: u32 = 3;
+pub const LARGE_INDIRECT_UNIT: u32 = 4;
+
+pub const ALBEDO_MAP_UNIT: u32 = 0;
+pub const METAGHNESS_MAP_UNIT: u32 = 1;
+pub const NORMAL_MAP_UNIT: u32 = 2;
+pub const OCCLUSION_MAP_UNIT: u32 = 3;
+pub const RADIANCE_MAP_UNIT: u32 = 4;
+pub const RADIANCE_BRICKS_MAP_UNIT: u32 = 5;
+// The first of the units of the clipmap cascades, one per cascade
+pub const CASCADE_RADIANCE_MAP_UNIT: u32 = 6;
+
+pub const ALBEDO_BRICKS_UNIT: u32 = 0;
+pub const RADIANCE_BRICKS_UNIT: u32 = 1;
+
+pub const TRIANGLE_COUNTER_BINDING: u32 = 0;
+pub const STATISTICS_COUNTER_BINDING: u32 = 1;
+pub const VERTEX_STORAGE_BINDING: u32 = 0;
+pub const INDEX_STORAGE_BINDING: u32 = 1;
+pub const FRAGMENT_LIST_BINDING: u32 = 2;
+pub const OCTREE_NODE_BINDING: u32 = 3;
+pub const VISIBLE_VOXELS_BINDING: u32 = 4;
+pub const VOXEL_SUMS_BINDING: u32 = 5;
+pub const LIGHTS_BINDING: u32 = 6;
+pub const LIGHT_CLUSTERS_BINDING: u32 = 7;
+
+// Vertex inputs, every vertex shader declares the ones it reads at these locations so the vertex
+// arrays don't depend on a program that can be reloaded
+pub const POSITION_ATTRIBUTE: u32 = 0;
+pub const TEX_COORD_ATTRIBUTE: u32 = 1;
+pub const NORMAL_ATTRIBUTE: u32 = 2;
+pub const TANGENT_ATTRIBUTE: u32 = 3;
+// The packed voxel of a voxel view instance
+pub const VOXEL_ATTRIBUTE: u32 = 0;
+
+pub static VERTEX_ATTRIBUTES: [(&str, u32); 5] = [
+	("aPosition", POSITION_ATTRIBUTE),
+	("aTexCoord", TEX_COORD_ATTRIBUTE),
+	("aNormal", NORMAL_ATTRIBUTE),
+	("aTangent", TANGENT_ATTRIBUTE),
+	("a_voxel", VOXEL_ATTRIBUTE),
+];
+
+pub static NO_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[],
+	bindings: &[],
+};
+
+pub static PBR_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		LIGHT_COUNT.slot(),
+		CLUSTER_DEPTH.slot(),
+		VIEWPORT_SIZE.slot(),
+		VIEW.slot(),
+	],
+	bindings: &[
+		binding("albedo_map", Sampler, ALBEDO_MAP_UNIT),
+		binding("metaghness_map", Sampler, METAGHNESS_MAP_UNIT),
+		binding("normal_map", Sampler, NORMAL_MAP_UNIT),
+		binding("occlusion_map", Sampler, OCCLUSION_MAP_UNIT),
+		binding("u_radiance", Sampler, RADIANCE_MAP_UNIT),
+		binding("u_radiance_bricks", Sampler, RADIANCE_BRICKS_MAP_UNIT),
+		binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING),
+		binding("u_cascades", Sampler, CASCADE_RADIANCE_MAP_UNIT),
+		binding("Lights", StorageBlock, LIGHTS_BINDING),
+		binding("LightClusters", StorageBlock, LIGHT_CLUSTERS_BINDING),
+	],
+};
+
+pub static LIGHT_CULL_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		LIGHT_COUNT.slot(),
+		CLUSTER_DEPTH.slot(),
+		VIEW.slot(),
+		INVERSE_PROJECTION.slot(),
+	],
+	bindings: &[
+		binding("Lights", StorageBlock, LIGHTS_BINDING),
+		binding("LightClusters", StorageBlock, LIGHT_CLUSTERS_BINDING),
+	],
+};
+
+pub static VOXEL_VIEW_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[VIEW_MIP.slot()],
+	bindings: &[binding("volume", Sampler, ALBEDO_UNIT)],
+};
+
+pub static VOXEL_COMPACT_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[RESOLUTION.slot(), VIEW_MIP.slot(), ALPHA_THRESHOLD.slot()],
+	bindings: &[
+		binding("u_volume", Sampler, ALBEDO_UNIT),
+		binding("VisibleVoxels", StorageBlock, VISIBLE_VOXELS_BINDING),
+	],
+};
+
+pub static VOXEL_SLICE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		VIEW_MIP.slot(),
+		ALPHA_THRESHOLD.slot(),
+		SLICE_AXIS.slot(),
+		SLICE_INDEX.slot(),
+	],
+	bindings: &[
+		binding("u_volume", Sampler, ALBEDO_UNIT),
+		binding("u_slice", Image, SLICE_UNIT),
+	],
+};
+
+pub static VOXEL_RESOLVE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[RESOLUTION.slot()],
+	bindings: &[
+		binding("u_voxel_albedo", Image, ALBEDO_UNIT),
+		binding("u_voxel_normal", Image, NORMAL_UNIT),
+		binding("u_voxel_emission", Image, EMISSION_UNIT),
+		binding("VoxelSums", StorageBlock, VOXEL_SUMS_BINDING),
+	],
+};
+
+pub static VOXELIZE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		RESOLUTION.slot(),
+		PV.slot(),
+		EXPAND_TRIANGLES.slot(),
+		COLLECT_STATISTICS.slot(),
+		STORE_FRAGMENTS.slot(),
+		ACCUMULATE_SUMS.slot(),
+		NORMAL_MAPPING.slot(),
+		VOXEL_OFFSET.slot(),
+		REGION_MIN.slot(),
+		REGION_MAX.slot(),
+	],
+	bindings: &[
+		binding("u_voxel_albedo", Image, ALBEDO_UNIT),
+		binding("u_voxel_normal", Image, NORMAL_UNIT),
+		binding("u_voxel_emission", Image, EMISSION_UNIT),
+		binding("albedo_map", Sampler, ALBEDO_MAP_UNIT),
+		binding("normal_map", Sampler, NORMAL_MAP_UNIT),
+		binding(
+			"u_stat_fragments",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("VoxelSums", StorageBlock, VOXEL_SUMS_BINDING),
+	],
+};
+
+pub static CLASSIFY_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		RESOLUTION.slot(),
+		PV.slot(),
+		CUTOFF.slot(),
+		COLLECT_STATISTICS.slot(),
+		STORE_FRAGMENTS.slot(),
+		ACCUMULATE_SUMS.slot(),
+		NORMAL_MAPPING.slot(),
+		VOXEL_OFFSET.slot(),
+		REGION_MIN.slot(),
+		REGION_MAX.slot(),
+	],
+	bindings: &[
+		binding("u_voxel_albedo", Image, ALBEDO_UNIT),
+		binding("u_voxel_normal", Image, NORMAL_UNIT),
+		binding("u_voxel_emission", Image, EMISSION_UNIT),
+		binding("largeIdx", Image, LARGE_INDICES_UNIT),
+		binding("largeIndirectElement", Image, LARGE_INDIRECT_UNIT),
+		binding("albedo_map", Sampler, ALBEDO_MAP_UNIT),
+		binding("normal_map", Sampler, NORMAL_MAP_UNIT),
+		binding("u_large_tri_count", AtomicCounter, TRIANGLE_COUNTER_BINDING),
+		binding(
+			"u_stat_large_triangles",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+		binding(
+			"u_stat_small_triangles",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+		binding(
+			"u_stat_fragments",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("VoxelSums", StorageBlock, VOXEL_SUMS_BINDING),
+	],
+};
+
+pub static VOXELIZE_COMPUTE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		RESOLUTION.slot(),
+		PV.slot(),
+		COLLECT_STATISTICS.slot(),
+		STORE_FRAGMENTS.slot(),
+		ACCUMULATE_SUMS.slot(),
+		NORMAL_MAPPING.slot(),
+		VOXEL_OFFSET.slot(),
+		REGION_MIN.slot(),
+		REGION_MAX.slot(),
+	],
+	bindings: &[
+		binding("Vertices", StorageBlock, VERTEX_STORAGE_BINDING),
+		binding("Indices", StorageBlock, INDEX_STORAGE_BINDING),
+		binding("u_voxel_albedo", Image, ALBEDO_UNIT),
+		binding("u_voxel_normal", Image, NORMAL_UNIT),
+		binding("u_voxel_emission", Image, EMISSION_UNIT),
+		binding("albedo_map", Sampler, ALBEDO_MAP_UNIT),
+		binding("normal_map", Sampler, NORMAL_MAP_UNIT),
+		binding(
+			"u_stat_fragments",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("VoxelSums", StorageBlock, VOXEL_SUMS_BINDING),
+	],
+};
+
+pub static RADIANCE_INJECTION_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		VOXEL_OFFSET.slot(),
+		DYNAMIC_OVERLAY.slot(),
+		LIGHT_COUNT.slot(),
+	],
+	bindings: &[
+		binding("u_voxel_albedo", Sampler, ALBEDO_UNIT),
+		binding("u_voxel_normal", Sampler, NORMAL_UNIT),
+		binding("u_voxel_emission", Sampler, EMISSION_UNIT),
+		binding("u_voxel_radiance", Image, RADIANCE_UNIT),
+		binding("u_overlay_albedo", Sampler, OVERLAY_ALBEDO_UNIT),
+		binding("u_overlay_normal", Sampler, OVERLAY_NORMAL_UNIT),
+		binding("u_overlay_emission", Sampler, OVERLAY_EMISSION_UNIT),
+		binding("Lights", StorageBlock, LIGHTS_BINDING),
+	],
+};
+
+pub static COUNT_VOXELS_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[RESOLUTION.slot()],
+	bindings: &[
+		binding("u_voxel_albedo", Sampler, ALBEDO_UNIT),
+		binding(
+			"u_stat_occupied_voxels",
+			AtomicCounter,
+			STATISTICS_COUNTER_BINDING,
+		),
+	],
+};
+
+pub static MIPMAP_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[CHILD_MIP_DIMENSION.slot()],
+	bindings: &[
+		binding("parent_volume", Sampler, 0),
+		binding("child_volume", Image, 1),
+	],
+};
+
+pub static OCTREE_FLAG_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[RESOLUTION.slot(), OCTREE_LEVEL.slot()],
+	bindings: &[
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING),
+	],
+};
+
+pub static OCTREE_ALLOCATE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[NODE_OFFSET.slot(), NODE_COUNT.slot(), NODE_CAPACITY.slot()],
+	bindings: &[binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING)],
+};
+
+pub static OCTREE_STORE_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		RESOLUTION.slot(),
+		OCTREE_LEVEL.slot(),
+		BRICK_POOL_WIDTH.slot(),
+	],
+	bindings: &[
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING),
+		binding("u_albedo_bricks", Image, ALBEDO_BRICKS_UNIT),
+	],
+};
+
+pub static OCTREE_INJECT_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		RESOLUTION.slot(),
+		OCTREE_LEVEL.slot(),
+		BRICK_POOL_WIDTH.slot(),
+		LIGHT_COUNT.slot(),
+	],
+	bindings: &[
+		binding("FragmentList", StorageBlock, FRAGMENT_LIST_BINDING),
+		binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING),
+		binding("u_radiance_bricks", Image, RADIANCE_BRICKS_UNIT),
+		binding("Lights", StorageBlock, LIGHTS_BINDING),
+	],
+};
+
+pub static OCTREE_FILTER_LAYOUT: ProgramLayout = ProgramLayout {
+	uniforms: &[
+		NODE_OFFSET.slot(),
+		NODE_COUNT.slot(),
+		BRICK_POOL_WIDTH.slot(),
+		RESOLVE_COVERAGE.slot(),
+	],
+	bindings: &[
+		binding("OctreeNodes", StorageBlock, OCTREE_NODE_BINDING),
+		binding("u_albedo_bricks", Image, ALBEDO_BRICKS_UNIT),
+		binding("u_radiance_bricks", Image, RADIANCE_BRICKS_UNIT),
+	],
+};
+
+// Compares the layout with what the linker assigned. Uniforms the compiler optimized away aren't
+// reported, setting them is harmless
+pub fn validate_layout(program: u32, layout: &ProgramLayout) -> Result<(), String> {
+	let mut errors = Vec::new();
+
+	for uniform in layout.uniforms {
+		let index = match resource_index(program, gl::UNIFORM, uniform.name) {
+			Some(index) => index,
+			None => continue,
+		};
+
+		let location = resource_property(program, gl::UNIFORM, index, gl::LOCATION);
+		let gl_type = resource_property(program, gl::UNIFORM, index, gl::TYPE);
+		if location != uniform.location {
+			errors.push(format!(
+				"'{}' is at location {} but the renderer sets location {}",
+				uniform.name, location, uniform.location
+			));
+		}
+		if gl_type as u32 != uniform.gl_type {
+			errors.push(format!(
+				"'{}' has GL type 0x{:X} but the renderer sets 0x{:X}",
+				uniform.name, gl_type, uniform.gl_type
+			));
+		}
+	}
+
+	for binding in layout.bindings {
+		let unit = match binding.kind {
+			Sampler | Image => resource_index(program, gl::UNIFORM, binding.name).map(|index| {
+				let location = resource_property(program, gl::UNIFORM, index, gl::LOCATION);
+				let mut unit = 0;
+				unsafe {
+					gl::GetUniformiv(program, location, &mut unit);
+				}
+				unit
+			}),
+			AtomicCounter => resource_index(program, gl::UNIFORM, binding.name).map(|index| {
+				let buffer =
+					resource_property(program, gl::UNIFORM, index, gl::ATOMIC_COUNTER_BUFFER_INDEX);
+				resource_property(
+					program,
+					gl::ATOMIC_COUNTER_BUFFER,
+					buffer as u32,
+					gl::BUFFER_BINDING,
+				)
+			}),
+			StorageBlock => {
+				resource_index(program, gl::SHADER_STORAGE_BLOCK, binding.name).map(|index| {
+					resource_property(program, gl::SHADER_STORAGE_BLOCK, index, gl::BUFFER_BINDING)
+				})
+			}
+		};
+
+		if let Some(unit) = unit {
+			if unit as u32 != binding.unit {
+				errors.push(format!(
+					"{:?} '{}' is bound to {} but the renderer uses {}",
+					binding.kind, binding.name, unit, binding.unit
+				));
+			}
+		}
+	}
+
+	for (name, expected) in VERTEX_ATTRIBUTES.iter() {
+		if let Some(index) = resource_index(program, gl::PROGRAM_INPUT, name) {
+			let location = resource_property(program, gl::PROGRAM_INPUT, index, gl::LOCATION);
+			if location as u32 != *expected {
+				errors.push(format!(
+					"'{}' is at location {} but the vertex arrays use {}",
+					name, location, expected
+				));
+			}
+		}
+	}
+
+	if errors.is_empty() {
+		Ok(())
+	} else {
+		Err(errors.join("\n"))
+	}
+}
+
+fn resource_index(program: u32, interface: u32, name: &str) -> Option<u32> {
+	let name = CString::new(name).ok()?;
+	let index = unsafe { gl::GetProgramResourceIndex(program, interface, name.as_ptr()) };
+
+	if index == gl::INVALID_INDEX {
+		None
+	} else {
+		Some(index)
+	}
+}
+
+fn resource_property(program: u32, interface: u32, index: u32, property: u32) -> i32 {
+	let mut value = 0;
+	unsafe {
+		gl::GetProgramResourceiv(
+			program,
+			interface,
+			index,
+			1,
+			&property,
+			1,
+			std::ptr::null_mut(),
+			&mut value,
+		);
+	}
+
+	value
+}
@@ -0,0 +1,148 @@
+use crate::renderer_utils::{build_program, ProgramDescription, ProgramId};
+use crate::shader_sources::ShaderSources;
+use gl_helpers::GLProgram;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct WatchedProgram {
+	description: &'static ProgramDescription,
+	// Every stage and everything they include, with the modification time last seen
+	files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl WatchedProgram {
+	fn new(description: &'static ProgramDescription, directory: &Path) -> WatchedProgram {
+		let mut watched = WatchedProgram {
+			description,
+			files: Vec::new(),
+		};
+		watched.refresh(directory);
+
+		watched
+	}
+
+	fn is_stale(&self) -> bool {
+		self
+			.files
+			.iter()
+			.any(|(path, modified)| modification_time(path) != *modified)
+	}
+
+	// Includes can be added or removed by an edit, so the dependencies are scanned again
+	fn refresh(&mut self, directory: &Path) {
+		let mut files: Vec<String> = self
+			.description
+			.stages
+			.iter()
+			.map(|(_, file)| file.to_string())
+			.collect();
+
+		let mut i = 0;
+		while i < files.len() {
+			if self.description.includes {
+				if let Ok(source) = fs::read_to_string(directory.join(&files[i])) {
+					for include in parse_includes(&source) {
+						if !files.contains(&include) {
+							files.push(include);
+						}
+					}
+				}
+			}
+			i += 1;
+		}
+
+		self.files = files
+			.iter()
+			.map(|file| {
+				let path = directory.join(file);
+				let modified = modification_time(&path);
+				(path, modified)
+			})
+			.collect();
+	}
+}
+
+// Only used when the shaders are read from a directory, embedded ones can't change
+pub struct ShaderManager {
+	directory: PathBuf,
+	programs: Vec<WatchedProgram>,
+	errors: BTreeMap<&'static str, String>,
+	last_poll: Instant,
+}
+
+impl ShaderManager {
+	pub fn new(directory: &Path, descriptions: &[&'static ProgramDescription]) -> ShaderManager {
+		ShaderManager {
+			directory: directory.to_owned(),
+			programs: descriptions
+				.iter()
+				.map(|d| WatchedProgram::new(d, directory))
+				.collect(),
+			errors: BTreeMap::new(),
+			last_poll: Instant::now(),
+		}
+	}
+
+	// Rebuilds the programs whose sources or includes changed since the last poll. A program
+	// that fails to build is left out, so the caller keeps using the previous one
+	pub fn poll(&mut self) -> Vec<(ProgramId, GLProgram)> {
+		if self.last_poll.elapsed() < POLL_INTERVAL {
+			return Vec::new();
+		}
+		self.last_poll = Instant::now();
+
+		let sources = ShaderSources::Directory(self.directory.clone());
+		let mut rebuilt = Vec::new();
+		for watched in self
+			.programs
+			.iter_mut()
+			.filter(|watched| watched.is_stale())
+		{
+			watched.refresh(&self.directory);
+
+			let name = watched.description.name;
+			match build_program(&sources, watched.description) {
+				Ok(program) => {
+					println!("Reloaded the '{}' program", name);
+					self.errors.remove(name);
+					rebuilt.push((watched.description.id, program));
+				}
+				Err(log) => {
+					println!("Couldn't reload the '{}' program:\n{}", name, log);
+					self.errors.insert(name, log);
+				}
+			}
+		}
+
+		rebuilt
+	}
+
+	// Build logs of the programs that currently fail, by program name
+	pub fn errors(&self) -> &BTreeMap<&'static str, String> {
+		&self.errors
+	}
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path)
+		.and_then(|metadata| metadata.modified())
+		.ok()
+}
+
+fn parse_includes(source: &str) -> Vec<String> {
+	source
+		.lines()
+		.map(str::trim)
+		.filter(|line| line.starts_with("#include"))
+		.map(|line| {
+			line["#include".len()..]
+				.trim()
+				.trim_matches(|c| c == '<' || c == '>' || c == '"')
+				.to_owned()
+		})
+		.collect()
+}
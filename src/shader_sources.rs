@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Compiled into the binary so it runs from any working directory. Every file of src/shaders must be
+// listed, the .glsl ones are the files shaders can #include
+const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+	("classify.frag", include_str!("shaders/classify.frag")),
+	("classify.geom", include_str!("shaders/classify.geom")),
+	("classify.vert", include_str!("shaders/classify.vert")),
+	(
+		"count_voxels.comp",
+		include_str!("shaders/count_voxels.comp"),
+	),
+	("depth_pass.frag", include_str!("shaders/depth_pass.frag")),
+	("depth_pass.vert", include_str!("shaders/depth_pass.vert")),
+	(
+		"fragment_list.glsl",
+		include_str!("shaders/fragment_list.glsl"),
+	),
+	(
+		"light_clusters.glsl",
+		include_str!("shaders/light_clusters.glsl"),
+	),
+	("light_cull.comp", include_str!("shaders/light_cull.comp")),
+	("lights.glsl", include_str!("shaders/lights.glsl")),
+	("mipmap.comp", include_str!("shaders/mipmap.comp")),
+	(
+		"octree_allocate.comp",
+		include_str!("shaders/octree_allocate.comp"),
+	),
+	(
+		"octree_filter.comp",
+		include_str!("shaders/octree_filter.comp"),
+	),
+	("octree_flag.comp", include_str!("shaders/octree_flag.comp")),
+	(
+		"octree_inject.comp",
+		include_str!("shaders/octree_inject.comp"),
+	),
+	(
+		"octree_store.comp",
+		include_str!("shaders/octree_store.comp"),
+	),
+	("octree.glsl", include_str!("shaders/octree.glsl")),
+	("pbr.frag", include_str!("shaders/pbr.frag")),
+	("pbr.vert", include_str!("shaders/pbr.vert")),
+	(
+		"radiance_injection.comp",
+		include_str!("shaders/radiance_injection.comp"),
+	),
+	("shared.glsl", include_str!("shaders/shared.glsl")),
+	(
+		"triangle_voxelization.glsl",
+		include_str!("shaders/triangle_voxelization.glsl"),
+	),
+	(
+		"volume_bounds.frag",
+		include_str!("shaders/volume_bounds.frag"),
+	),
+	(
+		"volume_bounds.vert",
+		include_str!("shaders/volume_bounds.vert"),
+	),
+	(
+		"voxel_compact.comp",
+		include_str!("shaders/voxel_compact.comp"),
+	),
+	(
+		"voxel_lighting.glsl",
+		include_str!("shaders/voxel_lighting.glsl"),
+	),
+	(
+		"voxel_region.glsl",
+		include_str!("shaders/voxel_region.glsl"),
+	),
+	(
+		"voxel_resolve.comp",
+		include_str!("shaders/voxel_resolve.comp"),
+	),
+	("voxel_slice.comp", include_str!("shaders/voxel_slice.comp")),
+	("voxel_sums.glsl", include_str!("shaders/voxel_sums.glsl")),
+	("voxel_view.frag", include_str!("shaders/voxel_view.frag")),
+	("voxel_view.vert", include_str!("shaders/voxel_view.vert")),
+	("voxelize.comp", include_str!("shaders/voxelize.comp")),
+	("voxelize.frag", include_str!("shaders/voxelize.frag")),
+	("voxelize.geom", include_str!("shaders/voxelize.geom")),
+	("voxelize.vert", include_str!("shaders/voxelize.vert")),
+];
+
+#[derive(Clone)]
+pub enum ShaderSources {
+	Embedded,
+	// Read from disk at runtime, which also enables hot reloading
+	Directory(PathBuf),
+}
+
+impl ShaderSources {
+	pub fn new(directory: Option<PathBuf>) -> ShaderSources {
+		match directory {
+			Some(directory) => ShaderSources::Directory(directory),
+			None => ShaderSources::Embedded,
+		}
+	}
+
+	pub fn read(&self, file: &str) -> Result<String, String> {
+		match self {
+			ShaderSources::Embedded => EMBEDDED_SHADERS
+				.iter()
+				.find(|(name, _)| *name == file)
+				.map(|(_, source)| source.to_string())
+				.ok_or_else(|| format!("There's no embedded shader named '{}'", file)),
+			ShaderSources::Directory(directory) => fs::read_to_string(directory.join(file))
+				.map_err(|e| format!("Couldn't read '{}': {}", directory.join(file).display(), e)),
+		}
+	}
+
+	// The files shaders can #include
+	pub fn include_files() -> impl Iterator<Item = &'static str> {
+		EMBEDDED_SHADERS
+			.iter()
+			.map(|(name, _)| *name)
+			.filter(|name| name.ends_with(".glsl"))
+	}
+
+	pub fn directory(&self) -> Option<&Path> {
+		match self {
+			ShaderSources::Embedded => None,
+			ShaderSources::Directory(directory) => Some(directory),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_shader_file_is_embedded() {
+		let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders");
+		let mut files: Vec<String> = fs::read_dir(&directory)
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+			.collect();
+		files.sort();
+
+		let mut embedded: Vec<&str> = EMBEDDED_SHADERS.iter().map(|(name, _)| *name).collect();
+		embedded.sort();
+		assert_eq!(
+			files, embedded,
+			"EMBEDDED_SHADERS doesn't list the files of src/shaders"
+		);
+	}
+}
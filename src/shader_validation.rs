@@ -0,0 +1,253 @@
+// Checks the shaders without a GPU: every stage is expanded the same way the renderer does it and
+// parsed, then the explicit layouts are compared against the program layouts the renderer binds with
+use crate::renderer_utils::*;
+use crate::shader_layout::VERTEX_ATTRIBUTES;
+use crate::shader_sources::ShaderSources;
+use glsl::parser::Parse;
+use glsl::syntax::{
+	Declaration, Expr, ExternalDeclaration, LayoutQualifierSpec, NonEmpty, StorageQualifier,
+	TranslationUnit, TypeQualifier, TypeQualifierSpec,
+};
+
+// A uniform, image, atomic counter or storage block of one stage
+struct Declared {
+	name: String,
+	location: Option<i32>,
+	binding: Option<i32>,
+}
+
+fn parse_program(description: &ProgramDescription) -> Vec<(&'static str, TranslationUnit)> {
+	let stages = expand_program(&ShaderSources::Embedded, description)
+		.unwrap_or_else(|log| panic!("Couldn't expand '{}': {}", description.name, log));
+
+	stages
+		.iter()
+		.zip(description.stages.iter())
+		.map(|((_, source), (_, file))| {
+			let unit = TranslationUnit::parse(source)
+				.unwrap_or_else(|e| panic!("Couldn't parse '{}':\n{:?}", file, e));
+			(*file, unit)
+		})
+		.collect()
+}
+
+fn layout_value(qualifier: &TypeQualifier, key: &str) -> Option<i32> {
+	qualifier
+		.qualifiers
+		.0
+		.iter()
+		.filter_map(|spec| match spec {
+			TypeQualifierSpec::Layout(layout) => Some(layout),
+			_ => None,
+		})
+		.flat_map(|layout| layout.ids.0.iter())
+		.find_map(|id| match id {
+			LayoutQualifierSpec::Identifier(name, Some(value)) if name.as_str() == key => match **value {
+				Expr::IntConst(value) => Some(value),
+				_ => None,
+			},
+			_ => None,
+		})
+}
+
+fn is_uniform_or_buffer(qualifier: &TypeQualifier) -> bool {
+	qualifier.qualifiers.0.iter().any(|spec| match spec {
+		TypeQualifierSpec::Storage(StorageQualifier::Uniform) => true,
+		TypeQualifierSpec::Storage(StorageQualifier::Buffer) => true,
+		_ => false,
+	})
+}
+
+fn is_input(qualifier: &TypeQualifier) -> bool {
+	qualifier.qualifiers.0.iter().any(|spec| match spec {
+		TypeQualifierSpec::Storage(StorageQualifier::In) => true,
+		_ => false,
+	})
+}
+
+// The vertex inputs of a stage with their explicit locations
+fn inputs(unit: &TranslationUnit) -> Vec<Declared> {
+	let TranslationUnit(NonEmpty(externals)) = unit;
+
+	externals
+		.iter()
+		.filter_map(|external| match external {
+			ExternalDeclaration::Declaration(Declaration::InitDeclaratorList(list)) => {
+				let qualifier = list.head.ty.qualifier.as_ref()?;
+				let name = list.head.name.as_ref()?;
+				if !is_input(qualifier) {
+					return None;
+				}
+
+				Some(Declared {
+					name: name.as_str().to_owned(),
+					location: layout_value(qualifier, "location"),
+					binding: None,
+				})
+			}
+			_ => None,
+		})
+		.collect()
+}
+
+fn declarations(unit: &TranslationUnit) -> Vec<Declared> {
+	let TranslationUnit(NonEmpty(externals)) = unit;
+
+	externals
+		.iter()
+		.filter_map(|external| match external {
+			ExternalDeclaration::Declaration(Declaration::InitDeclaratorList(list)) => {
+				let qualifier = list.head.ty.qualifier.as_ref()?;
+				let name = list.head.name.as_ref()?;
+				if !is_uniform_or_buffer(qualifier) {
+					return None;
+				}
+
+				Some(Declared {
+					name: name.as_str().to_owned(),
+					location: layout_value(qualifier, "location"),
+					binding: layout_value(qualifier, "binding"),
+				})
+			}
+			ExternalDeclaration::Declaration(Declaration::Block(block)) => Some(Declared {
+				name: block.name.as_str().to_owned(),
+				location: None,
+				binding: layout_value(&block.qualifier, "binding"),
+			}),
+			_ => None,
+		})
+		.collect()
+}
+
+fn program_declarations(description: &ProgramDescription) -> Vec<(&'static str, Declared)> {
+	parse_program(description)
+		.iter()
+		.flat_map(|(file, unit)| {
+			declarations(unit)
+				.into_iter()
+				.map(move |declared| (*file, declared))
+		})
+		.collect()
+}
+
+// At least one stage must declare `name` with an explicit value, and all that do must agree with
+// the layout
+fn check_layout(
+	description: &ProgramDescription,
+	name: &str,
+	expected: i32,
+	value: impl Fn(&Declared) -> Option<i32>,
+) {
+	let declarations = program_declarations(description);
+	let explicit: Vec<_> = declarations
+		.iter()
+		.filter(|(_, declared)| declared.name == name)
+		.filter_map(|(file, declared)| value(declared).map(|value| (file, value)))
+		.collect();
+
+	assert!(
+		!explicit.is_empty(),
+		"'{}' isn't declared with an explicit layout by any stage of '{}'",
+		name,
+		description.name
+	);
+
+	for (file, value) in explicit {
+		assert_eq!(
+			value, expected,
+			"'{}' in '{}' doesn't match the layout of '{}'",
+			name, file, description.name
+		);
+	}
+}
+
+#[test]
+fn every_program_expands_and_parses() {
+	for description in ALL_PROGRAMS.iter() {
+		let stages = parse_program(description);
+		assert_eq!(stages.len(), description.stages.len());
+	}
+}
+
+#[test]
+fn stages_agree_on_shared_uniforms() {
+	fn agree(a: Option<i32>, b: Option<i32>) -> bool {
+		match (a, b) {
+			(Some(a), Some(b)) => a == b,
+			_ => true,
+		}
+	}
+
+	for description in ALL_PROGRAMS.iter() {
+		let declarations = program_declarations(description);
+		for (file, declared) in &declarations {
+			for (other_file, other) in &declarations {
+				if declared.name == other.name {
+					assert!(
+						agree(declared.location, other.location) && agree(declared.binding, other.binding),
+						"'{}' has a different layout in '{}' and '{}'",
+						declared.name,
+						file,
+						other_file
+					);
+				}
+			}
+		}
+	}
+}
+
+#[test]
+fn uniform_locations_match_the_layouts() {
+	for description in ALL_PROGRAMS.iter() {
+		for uniform in description.layout.uniforms {
+			check_layout(description, uniform.name, uniform.location, |declared| {
+				declared.location
+			});
+		}
+	}
+}
+
+#[test]
+fn bindings_match_the_layouts() {
+	for description in ALL_PROGRAMS.iter() {
+		for binding in description.layout.bindings {
+			check_layout(description, binding.name, binding.unit as i32, |declared| {
+				declared.binding
+			});
+		}
+	}
+}
+
+#[test]
+fn vertex_inputs_match_the_vertex_arrays() {
+	for description in ALL_PROGRAMS.iter() {
+		let stages = parse_program(description);
+		let vertex_stages = description
+			.stages
+			.iter()
+			.zip(stages.iter())
+			.filter(|((stage, _), _)| *stage == ShaderStage::Vertex);
+
+		for (_, (file, unit)) in vertex_stages {
+			for input in inputs(unit) {
+				let expected = VERTEX_ATTRIBUTES
+					.iter()
+					.find(|(name, _)| *name == input.name)
+					.unwrap_or_else(|| {
+						panic!(
+							"'{}' in '{}' isn't a known vertex attribute",
+							input.name, file
+						)
+					});
+
+				assert_eq!(
+					input.location,
+					Some(expected.1 as i32),
+					"'{}' in '{}' doesn't match the location of the vertex arrays",
+					input.name,
+					file
+				);
+			}
+		}
+	}
+}
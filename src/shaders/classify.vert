@@ -3,10 +3,12 @@
 layout (location = 0) in vec3 aPosition;
 layout (location = 1) in vec2 aTexCoord;
 layout (location = 2) in vec3 aNormal;
+layout (location = 3) in vec3 aTangent;
 
 out VSOUT {
 	vec3 w_position;
 	vec3 w_normal;
+	vec3 w_tangent;
 	vec2 uv;
 	int  id;
 } v_out;
@@ -25,7 +27,8 @@ void main() {
 	gl_Position = position;
 
 	v_out.w_position = to_voxel_space(position.xyz);
-	v_out.w_normal = normalize(vec3(model * vec4(aNormal, 1.0)));
+	v_out.w_normal = normalize(vec3(model * vec4(aNormal, 0.0)));
+	v_out.w_tangent = vec3(model * vec4(aTangent, 0.0));
 	v_out.uv = aTexCoord;
 	v_out.id = gl_VertexID;
 }
@@ -0,0 +1,35 @@
+// Voxel fragments appended by the voxelizers. Each one is the packed voxel position followed by the
+// albedo, normal and emission as rgba8
+layout(std430, binding = 2) buffer FragmentList {
+	// Every fragment the voxelizers tried to append, it can exceed the capacity
+	uint fragment_total;
+	uint fragment_capacity;
+	uvec2 fragment_padding;
+	uvec4 fragments[];
+};
+
+// 10 bits per axis, enough for a 1024^3 volume
+uint pack_voxel_position(ivec3 position) {
+	uvec3 p = uvec3(position) & 0x3FFu;
+	return p.x | (p.y << 10) | (p.z << 20);
+}
+
+ivec3 unpack_voxel_position(uint packed) {
+	return ivec3(packed & 0x3FFu, (packed >> 10) & 0x3FFu, (packed >> 20) & 0x3FFu);
+}
+
+uint stored_fragment_count() {
+	return min(fragment_total, fragment_capacity);
+}
+
+void append_fragment(ivec3 position, vec3 albedo, vec3 normal, vec3 emission) {
+	uint index = atomicAdd(fragment_total, 1u);
+	if (index < fragment_capacity) {
+		fragments[index] = uvec4(
+			pack_voxel_position(position),
+			packUnorm4x8(vec4(albedo, 1.0)),
+			packUnorm4x8(vec4(normal, 1.0)),
+			packUnorm4x8(vec4(emission, 1.0))
+		);
+	}
+}
@@ -0,0 +1,40 @@
+// The view frustum split into clusters, tiles of the screen sliced by depth, each listing the lights
+// that reach it
+
+// Match the constants of light_clusters.rs
+#define CLUSTERS_X 16
+#define CLUSTERS_Y 9
+#define CLUSTERS_Z 24
+#define MAX_CLUSTER_LIGHTS 127
+// The light count of a cluster followed by room for the indices of its lights
+#define CLUSTER_STRIDE 128
+
+layout(std430, binding = 7) restrict buffer LightClusters {
+	uint cluster_lights[];
+};
+
+// The depth the slices start at and the one they end at, they grow exponentially in between
+layout(location = 23) uniform vec2 u_cluster_depth;
+layout(location = 26) uniform mat4 u_view;
+
+uint cluster_index(uvec3 cluster) {
+	return (cluster.z * CLUSTERS_Y + cluster.y) * CLUSTERS_X + cluster.x;
+}
+
+// The depth the slice starts at, what is closer than the first slice belongs to it
+float slice_depth(uint slice) {
+	if(slice == 0) {
+		return 0.0;
+	}
+
+	return u_cluster_depth.x * pow(u_cluster_depth.y / u_cluster_depth.x, float(slice) / float(CLUSTERS_Z));
+}
+
+// The cluster of a point of the screen, in [0, 1], at `depth` from the camera
+uvec3 find_cluster(vec2 screen, float depth) {
+	uvec2 tile = uvec2(screen * vec2(CLUSTERS_X, CLUSTERS_Y));
+	float slice = log(max(depth, u_cluster_depth.x) / u_cluster_depth.x)
+		/ log(u_cluster_depth.y / u_cluster_depth.x) * float(CLUSTERS_Z);
+
+	return min(uvec3(tile, uint(slice)), uvec3(CLUSTERS_X - 1, CLUSTERS_Y - 1, CLUSTERS_Z - 1));
+}
@@ -0,0 +1,84 @@
+// The lights of the scene, evaluated the same way when shading the scene and lighting the voxels
+
+// Match LightKind in scene/light.rs
+#define LIGHT_POINT 0
+#define LIGHT_DIRECTIONAL 1
+#define LIGHT_SPOT 2
+#define LIGHT_RECTANGLE 3
+#define LIGHT_SPHERE 4
+
+// No light renders a shadow map yet
+#define NO_SHADOW -1
+
+// Matches GpuLight in light_clusters.rs
+struct Light {
+	vec3 position;
+	int kind;
+	// Where directional, spot and rectangle lights point
+	vec3 direction;
+	// Past it the light contributes nothing
+	float range;
+	// The color times the intensity
+	vec3 color;
+	// The shadow map of the light, or NO_SHADOW
+	int shadow_index;
+	// Width and height of rectangle lights, the radius of sphere lights in x
+	vec2 size;
+	// The cosines of the inner and outer spot cone angles
+	float cos_inner;
+	float cos_outer;
+};
+
+layout(std430, binding = 6) readonly restrict buffer Lights {
+	Light lights[];
+};
+
+layout(location = 25) uniform uint u_light_count;
+
+// Smoothly reaches zero at the range, so nothing past it needs to be lit
+float distance_falloff(float dist, float range) {
+	float falloff = 15.0 / (1.0 + 2.0 * dist + 0.5 * dist * dist);
+	float window = clamp(1.0 - pow(dist / range, 4.0), 0.0, 1.0);
+	return falloff * window * window;
+}
+
+// The point of a rectangle light closest to `position`
+vec3 closest_rectangle_point(Light light, vec3 position) {
+	vec3 normal = light.direction;
+	vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+	vec3 right = normalize(cross(up, normal));
+	up = cross(normal, right);
+
+	vec3 offset = position - light.position;
+	vec2 half_size = light.size * 0.5;
+	return light.position
+		+ right * clamp(dot(offset, right), -half_size.x, half_size.x)
+		+ up * clamp(dot(offset, up), -half_size.y, half_size.y);
+}
+
+// The light `i` sends to `position`, before the cosine term of the surface. `L` is set to the unit
+// direction towards the light
+vec3 incident_light(uint i, vec3 position, out vec3 L) {
+	Light light = lights[i];
+	if(light.kind == LIGHT_DIRECTIONAL) {
+		L = -light.direction;
+		return light.color;
+	}
+
+	vec3 target = light.kind == LIGHT_RECTANGLE ? closest_rectangle_point(light, position) : light.position;
+	vec3 to_light = target - position;
+	float dist = length(to_light);
+	L = to_light / max(dist, 1e-5);
+
+	float attenuation = 1.0;
+	if(light.kind == LIGHT_SPOT) {
+		attenuation = smoothstep(light.cos_outer, light.cos_inner, dot(-L, light.direction));
+	} else if(light.kind == LIGHT_RECTANGLE) {
+		// Rectangles only emit from their front
+		attenuation = max(dot(-L, light.direction), 0.0);
+	} else if(light.kind == LIGHT_SPHERE) {
+		dist = max(dist - light.size.x, 0.0);
+	}
+
+	return light.color * attenuation * distance_falloff(dist, light.range);
+}
@@ -0,0 +1,44 @@
+// Node pool of the sparse voxel octree. The root is node 0 and the children of a node are a tile
+// of 8 consecutive nodes. A node stores the index of its first child, or 0 when it has none
+layout(std430, binding = 3) buffer OctreeNodes {
+	uint tile_count;
+	uint nodes[];
+};
+
+// Set on a node while building, when a fragment says it needs children
+const uint NODE_FLAG = 0x80000000u;
+const uint NODE_CHILD_MASK = 0x7FFFFFFFu;
+
+uvec3 octant_of(vec3 local) {
+	return min(uvec3(local * 2.0), uvec3(1u));
+}
+
+// Walks down from the root to the node of `level` containing `position`, given in [0, 1) of the
+// volume. `local` ends up as the position inside that node. Fails when the branch is empty
+bool find_node(vec3 position, int level, out uint node, out vec3 local) {
+	node = 0u;
+	local = position;
+
+	for (int i = 0; i < level; i++) {
+		uint child = nodes[node] & NODE_CHILD_MASK;
+		if (child == 0u) {
+			return false;
+		}
+
+		uvec3 octant = octant_of(local);
+		node = child + octant.x + octant.y * 2u + octant.z * 4u;
+		local = local * 2.0 - vec3(octant);
+	}
+
+	return true;
+}
+
+// Every node owns a 2x2x2 brick in the brick pools, holding the values of its octants
+ivec3 brick_origin(uint node, int pool_width) {
+	uint width = uint(pool_width);
+	return 2 * ivec3(node % width, (node / width) % width, node / (width * width));
+}
+
+ivec3 brick_texel(uint node, vec3 local, int pool_width) {
+	return brick_origin(node, pool_width) + ivec3(octant_of(local));
+}
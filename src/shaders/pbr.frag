@@ -1,14 +1,12 @@
 #version 450
-#define MAX_LIGHTS 4
+#define MAX_CASCADES 4
+// Matches clipmap.rs, the coarser mips wouldn't stay aligned with the moving cascades
+#define CASCADE_MIPS 3
 
 #include <shared.glsl>
-
-uniform vec3 light_direction[MAX_LIGHTS];
-uniform vec3 light_position[MAX_LIGHTS];
-uniform vec3 light_color[MAX_LIGHTS];
-uniform float light_range[MAX_LIGHTS];
-
-uniform int num_lights;
+#include <octree.glsl>
+#include <lights.glsl>
+#include <light_clusters.glsl>
 
 uniform float time;
 uniform vec3 camera_position;
@@ -16,12 +14,28 @@ uniform vec3 camera_position;
 uniform vec3 u_volume_center;
 uniform vec3 u_volume_scale;
 uniform int u_width;
+layout(location = 24) uniform vec2 u_viewport_size;
+
+// Set when the radiance lives in the bricks of a sparse octree instead of u_radiance
+uniform bool u_octree;
+uniform int u_octree_depth;
+uniform int u_brick_pool_width;
+
+// Set when the radiance lives in clipmap cascades around the camera, the finest first. Each one
+// is stored toroidally: a voxel always lands on the texel given by its world voxel coordinate
+// modulo the resolution, so the cascades are sampled with repeating coordinates
+uniform bool u_clipmap;
+uniform int u_cascade_count;
+uniform vec3 u_cascade_min[MAX_CASCADES];
+uniform vec3 u_cascade_extent[MAX_CASCADES];
 
 uniform layout(binding = 0) sampler2D albedo_map;
 uniform layout(binding = 1) sampler2D metaghness_map;
 uniform layout(binding = 2) sampler2D normal_map;
 uniform layout(binding = 3) sampler2D occlusion_map;
 uniform layout(binding = 4) sampler3D u_radiance;
+uniform layout(binding = 5) sampler3D u_radiance_bricks;
+uniform layout(binding = 6) sampler3D u_cascades[MAX_CASCADES];
 
 in vec3 vw_position;
 in vec2 v_uv;
@@ -77,6 +91,96 @@ vec3 radiance_coordinate(vec3 w_position) {
 	return (((w_position - volume_corner) / (u_volume_scale)));
 }
 
+// Bricks don't share their borders with the neighboring nodes, so the filtering is clamped to the
+// node
+vec4 sample_brick(vec3 position, int level) {
+	if (any(lessThan(position, vec3(0.0))) || any(greaterThanEqual(position, vec3(1.0)))) {
+		return vec4(0.0);
+	}
+
+	uint node;
+	vec3 local;
+	if (!find_node(position, level, node, local)) {
+		return vec4(0.0);
+	}
+
+	vec3 texel = vec3(brick_origin(node, u_brick_pool_width)) + clamp(local * 2.0, vec3(0.5), vec3(1.5));
+	return textureLod(u_radiance_bricks, texel / float(2 * u_brick_pool_width), 0.0);
+}
+
+// Mip 0 is the deepest level of the octree, the same as the first mip of the dense volume
+vec4 sample_octree(vec3 position, float mip) {
+	float level = float(u_octree_depth - 1) - clamp(mip, 0.0, float(u_octree_depth - 1));
+	int coarse = int(floor(level));
+
+	vec4 radiance = sample_brick(position, coarse);
+	if (fract(level) > 0.0) {
+		radiance = mix(radiance, sample_brick(position, coarse + 1), fract(level));
+	}
+
+	return radiance;
+}
+
+// The sampler index has to be constant, which it isn't along a cone
+vec4 sample_cascade(int cascade, vec3 w_position, float mip) {
+	vec3 tc = w_position / u_cascade_extent[cascade];
+	switch (cascade) {
+	case 0: return textureLod(u_cascades[0], tc, mip);
+	case 1: return textureLod(u_cascades[1], tc, mip);
+	case 2: return textureLod(u_cascades[2], tc, mip);
+	default: return textureLod(u_cascades[3], tc, mip);
+	}
+}
+
+// Keeps a voxel away from the border of the cascade, where the voxels just wrapped around
+bool in_cascade(int cascade, vec3 w_position) {
+	float margin = u_cascade_extent[cascade].x / float(u_width);
+	vec3 local = w_position - u_cascade_min[cascade];
+	return all(greaterThan(local, vec3(margin))) && all(lessThan(local, vec3(u_cascade_extent[cascade] - margin)));
+}
+
+// Each cascade has voxels twice as large as the previous one, so a cone that has grown past the
+// voxels of a cascade continues in the next one. Positions outside a cascade fall through to the
+// coarser ones as well
+vec4 sample_clipmap(vec3 w_position, float diameter) {
+	float voxel_size = u_cascade_extent[0].x / float(u_width);
+	float level = log2(max(diameter / voxel_size, 1.0));
+
+	int cascade = min(int(level), u_cascade_count - 1);
+	while (cascade < u_cascade_count && !in_cascade(cascade, w_position)) {
+		cascade++;
+	}
+	if (cascade == u_cascade_count) {
+		return vec4(0.0);
+	}
+
+	float mip = clamp(level - float(cascade), 0.0, float(CASCADE_MIPS - 1));
+	return sample_cascade(cascade, w_position, mip);
+}
+
+// Same as ConeTrace, in world space and through the cascades
+vec4 ConeTraceClipmap(vec3 P, vec3 N, vec3 direction, float aperture) {
+	float voxel_size = u_cascade_extent[0].x / float(u_width);
+	vec3 origin = P + N * voxel_size * 2.0 * 1.414213;
+
+	float maxDistance = u_cascade_extent[u_cascade_count - 1].x * 0.5;
+	vec3 color = vec3(0.0);
+	float alpha = 0.0;
+	float t = voxel_size;
+	while (t < maxDistance && alpha < 1.0) {
+		float diameter = max(voxel_size, 2.0 * aperture * t);
+		vec4 radiance = sample_clipmap(origin + direction * t, diameter);
+
+		float a = 1 - alpha;
+		color += a * radiance.rgb;
+		alpha += a * radiance.a;
+
+		t += diameter * 0.5;
+	}
+
+	return vec4(color, alpha);
+}
+
 vec4 ConeTrace(sampler3D voxels, vec3 P,vec3 N, vec3 direction, float aperture) {
 	P = radiance_coordinate(P);
 	const float voxel_size = 1.0 / float(u_width);
@@ -91,7 +195,7 @@ vec4 ConeTrace(sampler3D voxels, vec3 P,vec3 N, vec3 direction, float aperture)
 		float mip = log2(diameter * voxel_size * 500.0);
 
 		vec3 tc = origin + direction * t;
-		vec4 radiance = textureLod(voxels, tc, min(mip, 6.0));
+		vec4 radiance = u_octree ? sample_octree(tc, min(mip, 6.0)) : textureLod(voxels, tc, min(mip, 6.0));
 
 		float a = 1 - alpha;
 		color += a * radiance.rgb;
@@ -103,8 +207,7 @@ vec4 ConeTrace(sampler3D voxels, vec3 P,vec3 N, vec3 direction, float aperture)
 	return vec4(color, alpha);
 }
 
-vec3 direct_lighting(vec3 Li, vec3 Lc, vec3 albedo, float roughness, float metalness, vec3 normal, float occlusion, vec3 V, vec3 F0) {
-	vec3 L = -normalize(Li);
+vec3 direct_lighting(vec3 L, vec3 Lc, vec3 albedo, float roughness, float metalness, vec3 normal, float occlusion, vec3 V, vec3 F0) {
 	vec3 H = normalize(V + L);
 
 	vec3 N = normal;
@@ -145,16 +248,19 @@ void main() {
 	vec3 F0 = vec3(0.04);
 	F0 = mix(F0, albedo, metalness);
 
+	// Only the lights listed by the cluster of the fragment can reach it
+	float depth = -(u_view * vec4(vw_position, 1.0)).z;
+	uint base = cluster_index(find_cluster(gl_FragCoord.xy / u_viewport_size, depth)) * CLUSTER_STRIDE;
+	uint light_count = cluster_lights[base];
+
 	vec3 direct = vec3(0.0);
-	for(int i = 0; i < num_lights; i++) {
-		vec3 Li = vw_position.xyz - light_position[i];
-		float dist = length(Li);
-		float attenuation = 1.0 / (1.0 + 2.0 * dist + 0.5 * dist * dist);
-		attenuation = clamp(attenuation, 0.0, 1.0);
-
-		vec3 radiance = direct_lighting(
-			Li,
-			light_color[i],
+	for(uint n = 0; n < light_count; n++) {
+		vec3 L;
+		vec3 light = incident_light(cluster_lights[base + 1 + n], vw_position.xyz, L);
+
+		direct += direct_lighting(
+			L,
+			light,
 			albedo,
 			roughness,
 			metalness,
@@ -163,15 +269,16 @@ void main() {
 			V,
 			F0
 		);
-
-		direct += 15.0 * radiance * attenuation;
 	}
 
 	vec4 radiance = vec4(0.0);
 	for(int i = 0; i < 9; i++) {
 		vec3 cone_dir = normalize(CONES[i] + normal);
 		cone_dir *= dot(cone_dir, normal) < 0 ? -1 : 1;
-		radiance += ConeTrace(u_radiance, vw_position, normal, cone_dir, tan(PI * 0.5 * 0.33));
+		float aperture = tan(PI * 0.5 * 0.33);
+		radiance += u_clipmap
+			? ConeTraceClipmap(vw_position, normal, cone_dir, aperture)
+			: ConeTrace(u_radiance, vw_position, normal, cone_dir, aperture);
 	}
 	radiance /= 9.0;
 
@@ -21,6 +21,15 @@ vec3 decode_normal(vec3 normal) {
 	return normal * 2.0f - vec3(1.0f);
 }
 
+// Tangent space normal mapping the way pbr.frag does it. `texel` is the normal map sample, `normal`
+// and `tangent` are in world space
+vec3 map_normal(vec3 texel, vec3 normal, vec3 tangent) {
+	vec3 N = normalize(normal);
+	vec3 T = normalize(tangent - dot(tangent, N) * N);
+	vec3 B = cross(N, T);
+	return normalize(mat3(T, B, N) * normalize(texel * 2.0 - 1.0));
+}
+
 bool is_outside_aabb(vec4 aabb, vec2 coord, int resolution) {
 	vec2 aabb_min = floor((aabb.xy * 0.5 + 0.5) * resolution);
 	vec2 aabb_max = ceil((aabb.zw * 0.5 + 0.5) * resolution);
@@ -0,0 +1,34 @@
+// Direct lighting of the voxels, shared by the dense and the octree light injection. lights.glsl
+// must be included first
+uniform vec3 u_volume_center;
+uniform vec3 u_volume_scale;
+
+vec3 world_coordinate(ivec3 image_position, ivec3 resolution) {
+	return (vec3(image_position) / vec3(resolution)) * u_volume_scale + u_volume_center - u_volume_scale * 0.505;
+}
+
+// The cosine term of a voxel, its normal weights the faces of the voxel that see the light
+float voxel_cosine(vec3 L, vec3 normal) {
+	// calculate directional normal attenuation
+	float XdotL = dot(vec3(1.0, 0.0, 0.0), L);
+	float YdotL = dot(vec3(0.0, 1.0, 0.0), L);
+	float ZdotL = dot(vec3(0.0, 0.0, 1.0), L);
+
+	XdotL = normal.x > 0.0 ? max(XdotL, 0.0) : max(-XdotL, 0.0);
+	YdotL = normal.y > 0.0 ? max(YdotL, 0.0) : max(-YdotL, 0.0);
+	ZdotL = normal.z > 0.0 ? max(ZdotL, 0.0) : max(-ZdotL, 0.0);
+
+	vec3 weight = normal * normal;
+	return XdotL * weight.x + YdotL * weight.y + ZdotL * weight.z;
+}
+
+vec3 voxel_radiance(vec3 w_voxel_position, vec3 albedo, vec3 normal) {
+	vec3 radiance = vec3(0.0);
+	for(uint i = 0; i < u_light_count; i++) {
+		vec3 L;
+		vec3 light = incident_light(i, w_voxel_position, L);
+		radiance += voxel_cosine(L, normal) * light * albedo;
+	}
+
+	return radiance;
+}
@@ -0,0 +1,14 @@
+// The part of the volume a voxelization pass writes to. Clipmap cascades are stored toroidally, so
+// every voxel is offset and wrapped around the resolution, and only the voxels inside the region
+// are written. A dense volume uses no offset and the whole volume as its region
+layout(location = 13) uniform ivec3 u_voxel_offset;
+layout(location = 14) uniform ivec3 u_region_min;
+layout(location = 15) uniform ivec3 u_region_max;
+
+bool in_region(ivec3 voxel) {
+	return all(greaterThanEqual(voxel, u_region_min)) && all(lessThan(voxel, u_region_max));
+}
+
+ivec3 wrap_voxel(ivec3 voxel, ivec3 resolution) {
+	return (voxel + u_voxel_offset) % resolution;
+}
@@ -0,0 +1,51 @@
+// Fixed point sums of the attributes of every voxel and how many samples were added to them. Unlike
+// the running average of image_average_rgba8, they don't round at every step. voxel_resolve.comp
+// divides them into the attribute textures
+layout(location = 21) uniform bool u_accumulate_sums;
+
+// Per voxel: the albedo, normal and emission sums packed two to a word, then the count
+const uint VOXEL_SUM_STRIDE = 6u;
+const uint VOXEL_COUNT_OFFSET = 5u;
+// Samples are added with the 8 bits of the textures, so a 16 bit sum holds 257 of them. Later
+// samples are still counted but left out of the sums
+const float SAMPLE_SCALE = 255.0;
+const uint MAX_SUM_SAMPLES = 257u;
+
+layout(std430, binding = 5) coherent restrict buffer VoxelSums {
+	uint voxel_sums[];
+};
+
+uint voxel_sum_index(ivec3 voxel, ivec3 resolution) {
+	return VOXEL_SUM_STRIDE * uint(voxel.x + resolution.x * (voxel.y + resolution.y * voxel.z));
+}
+
+// Two samples in the low and high halves of a word, a 16 bit sum never carries into the other
+uint pack_samples(float low, float high) {
+	uvec2 fixed_value = uvec2(round(clamp(vec2(low, high), 0.0, 1.0) * SAMPLE_SCALE));
+	return fixed_value.x | (fixed_value.y << 16);
+}
+
+void accumulate_voxel(ivec3 voxel, ivec3 resolution, vec3 albedo, vec3 normal, vec3 emission) {
+	uint index = voxel_sum_index(voxel, resolution);
+	if(atomicAdd(voxel_sums[index + VOXEL_COUNT_OFFSET], 1u) >= MAX_SUM_SAMPLES) {
+		return;
+	}
+
+	atomicAdd(voxel_sums[index + 0u], pack_samples(albedo.r, albedo.g));
+	atomicAdd(voxel_sums[index + 1u], pack_samples(albedo.b, normal.x));
+	atomicAdd(voxel_sums[index + 2u], pack_samples(normal.y, normal.z));
+	atomicAdd(voxel_sums[index + 3u], pack_samples(emission.r, emission.g));
+	atomicAdd(voxel_sums[index + 4u], pack_samples(emission.b, 0.0));
+}
+
+// The sum `sum` of the voxel, counting the nine sums in order across the words
+float voxel_sum(uint index, uint sum) {
+	return float((voxel_sums[index + sum / 2u] >> (16u * (sum % 2u))) & 0xffffu);
+}
+
+// The average of the three sums starting at `first`
+vec3 average_sum(uint index, uint first, uint count) {
+	vec3 sum =
+		vec3(voxel_sum(index, first), voxel_sum(index, first + 1u), voxel_sum(index, first + 2u));
+	return sum / (SAMPLE_SCALE * float(min(count, MAX_SUM_SAMPLES)));
+}
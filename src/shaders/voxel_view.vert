@@ -1,31 +1,28 @@
 #version 450 core
 
-layout (location = 0) in vec3 aPosition;
+// One instance per voxel listed by voxel_compact.comp
+layout (location = 0) in uint a_voxel;
 
-out VSOUT{
-	vec4 position;
-	vec3 w_position;
-	vec4 color;
-} v_out;
+out vec3 v_color;
 
 layout (binding = 0) uniform sampler3D volume;
 uniform mat4 mvp;
 uniform int resolution;
+layout(location = 17) uniform int u_mip;
 
-void main() {
-	float voxel_size = (1.0 / float(resolution));
+// A unit cube as a single triangle strip
+const vec3 CUBE[14] = vec3[](
+	vec3(1, 1, 0), vec3(0, 1, 0), vec3(1, 0, 0), vec3(0, 0, 0),
+	vec3(0, 0, 1), vec3(0, 1, 0), vec3(0, 1, 1), vec3(1, 1, 0),
+	vec3(1, 1, 1), vec3(1, 0, 0), vec3(1, 0, 1), vec3(0, 0, 1),
+	vec3(1, 1, 1), vec3(0, 1, 1)
+);
 
-	uint i = gl_VertexID % resolution;
-	uint j = (gl_VertexID / resolution) % resolution;
-	uint k = (gl_VertexID / resolution / resolution) % resolution;
+void main() {
+	float voxel_size = 1.0 / float(max(resolution >> u_mip, 1));
 
-	ivec3 texel_position = ivec3(i, j, k) + ivec3(aPosition * 0.0001);
-	vec4 color = texelFetch(volume, texel_position, 0);
-	// color.xyz += vec3((1.0 * gl_VertexID) / (resolution * resolution * resolution) * 1.0);
-	// color.a = 1.0;
-	gl_Position = mvp * vec4(texel_position * voxel_size, 1.0);
+	ivec3 texel_position = ivec3(a_voxel & 0x3FF, (a_voxel >> 10) & 0x3FF, (a_voxel >> 20) & 0x3FF);
+	v_color = texelFetch(volume, texel_position, u_mip).rgb;
 
-	v_out.position = gl_Position;
-	v_out.w_position = texel_position;
-	v_out.color = color;
+	gl_Position = mvp * vec4((vec3(texel_position) + CUBE[gl_VertexID]) * voxel_size, 1.0);
 }
@@ -1,9 +1,13 @@
 #version 450 core
 
 #include <shared.glsl>
+#include <fragment_list.glsl>
+#include <voxel_region.glsl>
+#include <voxel_sums.glsl>
 
 in vec3 gw_position;
 in vec3 gw_normal;
+in vec3 gw_tangent;
 in vec2 g_uv;
 in flat vec4 g_AABB;
 in flat int g_swizzle;
@@ -13,9 +17,15 @@ layout(binding = 1, r32ui) uniform volatile coherent restrict uimage3D u_voxel_n
 layout(binding = 2, r32ui) uniform volatile coherent restrict uimage3D u_voxel_emission;
 
 layout(binding = 0) uniform sampler2D albedo_map;
+layout(binding = 2) uniform sampler2D normal_map;
 
 layout(location = 0) uniform ivec3 u_resolution;
 layout(location = 2) uniform bool u_expand_triangle;
+layout(location = 4) uniform bool u_collect_statistics;
+layout(location = 5) uniform bool u_store_fragments;
+layout(location = 22) uniform bool u_normal_mapping;
+
+layout(binding = 1, offset = 8) uniform atomic_uint u_stat_fragments;
 
 void main() {
 	if(u_expand_triangle == true) {
@@ -24,17 +34,39 @@ void main() {
 		}
 	}
 
+	if(u_collect_statistics) {
+		atomicCounterIncrement(u_stat_fragments);
+	}
+
 	mat3 swizzle_matrix_inverse = inverse(swizzleLUT[g_swizzle]);
 
 	// Voxel position
 	vec3 pos = vec3(gl_FragCoord.xy, gl_FragCoord.z * u_resolution.z);
 	ivec3 position = ivec3(swizzle_matrix_inverse * pos);
+	if(!in_region(position)) {
+		return;
+	}
+	position = wrap_voxel(position, u_resolution);
 
 	vec3 albedo = texture(albedo_map, g_uv).rgb;
 	// vec3 albedo = texture(albedo_map, g_uv).rgb * 0.00001 + vec3(1.0, 0.0, 0.0);
-	vec3 normal = encode_normal(gw_normal);
+	vec3 normal = gw_normal;
+	if(u_normal_mapping) {
+		normal = map_normal(texture(normal_map, g_uv).rgb, gw_normal, gw_tangent);
+	}
+	normal = encode_normal(normal);
 	vec3 emission = vec3(0.0);
 
+	if(u_store_fragments) {
+		append_fragment(position, albedo, normal, emission);
+		return;
+	}
+
+	if(u_accumulate_sums) {
+		accumulate_voxel(position, u_resolution, albedo, normal, emission);
+		return;
+	}
+
 	image_average_rgba8(u_voxel_albedo, position, albedo);
 	image_average_rgba8(u_voxel_normal, position, normal);
 	image_average_rgba8(u_voxel_emission, position, emission);
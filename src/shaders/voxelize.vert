@@ -3,10 +3,12 @@
 layout (location = 0) in vec3 aPosition;
 layout (location = 1) in vec2 aTexCoord;
 layout (location = 2) in vec3 aNormal;
+layout (location = 3) in vec3 aTangent;
 
 out VSOUT {
 	vec3 w_position;
   vec3 w_normal;
+	vec3 w_tangent;
 	vec2 uv;
 } v_out;
 
@@ -18,6 +20,7 @@ void main() {
   gl_Position = w_position;
 
   v_out.w_position = w_position.xyz;
-  v_out.w_normal = normalize(vec3(model * vec4(aNormal, 1.0)));
+  v_out.w_normal = normalize(vec3(model * vec4(aNormal, 0.0)));
+  v_out.w_tangent = vec3(model * vec4(aTangent, 0.0));
   v_out.uv = aTexCoord;
 }
@@ -0,0 +1,176 @@
+use crate::capabilities::{Feature, GlCapabilities};
+use gl;
+use gl::types::*;
+
+// Counter layout of the statistics buffer, matches the offsets used in the shaders
+const LARGE_TRIANGLES: usize = 0;
+const SMALL_TRIANGLES: usize = 1;
+const FRAGMENTS: usize = 2;
+const OCCUPIED_VOXELS: usize = 3;
+const COUNTER_COUNT: usize = 4;
+
+// GL_ARB_pipeline_statistics_query, not exposed by the gl crate
+const VERTICES_SUBMITTED: u32 = 0x82EE;
+const PRIMITIVES_SUBMITTED: u32 = 0x82EF;
+const GEOMETRY_SHADER_INVOCATIONS: u32 = 0x887F;
+const GEOMETRY_SHADER_PRIMITIVES_EMITTED: u32 = 0x82F3;
+const FRAGMENT_SHADER_INVOCATIONS: u32 = 0x82F4;
+const COMPUTE_SHADER_INVOCATIONS: u32 = 0x82F5;
+const CLIPPING_OUTPUT_PRIMITIVES: u32 = 0x82F7;
+
+const PIPELINE_TARGET_COUNT: usize = 7;
+const PIPELINE_TARGETS: [u32; PIPELINE_TARGET_COUNT] = [
+	VERTICES_SUBMITTED,
+	PRIMITIVES_SUBMITTED,
+	GEOMETRY_SHADER_INVOCATIONS,
+	GEOMETRY_SHADER_PRIMITIVES_EMITTED,
+	CLIPPING_OUTPUT_PRIMITIVES,
+	FRAGMENT_SHADER_INVOCATIONS,
+	COMPUTE_SHADER_INVOCATIONS,
+];
+
+#[derive(Clone, Copy, Default)]
+pub struct PipelineStatistics {
+	pub vertices_submitted: u64,
+	pub primitives_submitted: u64,
+	pub geometry_shader_invocations: u64,
+	pub geometry_shader_primitives: u64,
+	pub clipping_output_primitives: u64,
+	pub fragment_shader_invocations: u64,
+	pub compute_shader_invocations: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct VoxelizationStatistics {
+	pub large_triangles: u32,
+	pub small_triangles: u32,
+	pub fragments: u32,
+	pub occupied_voxels: u32,
+	pub pipeline: Option<PipelineStatistics>,
+}
+
+pub struct StatisticsCounters {
+	handle: u32,
+}
+
+impl StatisticsCounters {
+	pub fn new() -> StatisticsCounters {
+		let mut handle = 0;
+		let data = [0u32; COUNTER_COUNT];
+
+		unsafe {
+			gl::CreateBuffers(1, &mut handle);
+			gl::NamedBufferData(
+				handle,
+				(data.len() * 4) as isize,
+				data.as_ptr() as *const GLvoid,
+				gl::DYNAMIC_READ,
+			);
+		}
+
+		StatisticsCounters { handle }
+	}
+
+	pub fn bind_unit(&self, unit: u32) {
+		unsafe {
+			gl::BindBufferBase(gl::ATOMIC_COUNTER_BUFFER, unit, self.handle);
+		}
+	}
+
+	pub fn clear(&self) {
+		let data = [0u32; COUNTER_COUNT];
+		unsafe {
+			gl::NamedBufferSubData(
+				self.handle,
+				0,
+				(data.len() * 4) as isize,
+				data.as_ptr() as *const GLvoid,
+			);
+		}
+	}
+
+	// Stalls until the voxelization has finished
+	fn read(&self) -> [u32; COUNTER_COUNT] {
+		let mut data = [0u32; COUNTER_COUNT];
+		unsafe {
+			gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+			gl::GetNamedBufferSubData(
+				self.handle,
+				0,
+				(data.len() * 4) as isize,
+				data.as_mut_ptr() as *mut GLvoid,
+			);
+		}
+
+		data
+	}
+}
+
+pub struct PipelineQueries {
+	query_ids: [u32; PIPELINE_TARGET_COUNT],
+}
+
+impl PipelineQueries {
+	pub fn new(capabilities: &GlCapabilities) -> Option<PipelineQueries> {
+		if !capabilities.supports(Feature::PipelineStatistics) {
+			return None;
+		}
+
+		let mut query_ids = [0u32; PIPELINE_TARGET_COUNT];
+		unsafe {
+			gl::GenQueries(query_ids.len() as i32, query_ids.as_mut_ptr());
+		}
+
+		Some(PipelineQueries { query_ids })
+	}
+
+	pub fn begin(&self) {
+		for (target, id) in PIPELINE_TARGETS.iter().zip(self.query_ids.iter()) {
+			unsafe {
+				gl::BeginQuery(*target, *id);
+			}
+		}
+	}
+
+	pub fn end(&self) {
+		for target in PIPELINE_TARGETS.iter() {
+			unsafe {
+				gl::EndQuery(*target);
+			}
+		}
+	}
+
+	fn read(&self) -> PipelineStatistics {
+		let mut results = [0u64; PIPELINE_TARGET_COUNT];
+		for (id, result) in self.query_ids.iter().zip(results.iter_mut()) {
+			unsafe {
+				gl::GetQueryObjectui64v(*id, gl::QUERY_RESULT, result);
+			}
+		}
+
+		PipelineStatistics {
+			vertices_submitted: results[0],
+			primitives_submitted: results[1],
+			geometry_shader_invocations: results[2],
+			geometry_shader_primitives: results[3],
+			clipping_output_primitives: results[4],
+			fragment_shader_invocations: results[5],
+			compute_shader_invocations: results[6],
+		}
+	}
+}
+
+pub fn read_statistics(
+	counters: &StatisticsCounters,
+	queries: Option<&PipelineQueries>,
+) -> VoxelizationStatistics {
+	let values = counters.read();
+
+	VoxelizationStatistics {
+		large_triangles: values[LARGE_TRIANGLES],
+		small_triangles: values[SMALL_TRIANGLES],
+		fragments: values[FRAGMENTS],
+		occupied_voxels: values[OCCUPIED_VOXELS],
+		pipeline: queries.map(PipelineQueries::read),
+	}
+}
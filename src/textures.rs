@@ -1,18 +1,119 @@
-use crate::gpu_model::GpuPrimitive;
+use crate::clipmap::{Clipmap, MAX_CASCADES};
+use crate::octree::SparseOctree;
 use crate::renderer_utils::*;
 use gl;
 use gl_helpers::*;
-use glm::UVec3;
 use nalgebra_glm as glm;
+use serde::Deserialize;
 use std::mem;
+use std::ptr;
+use std::str::FromStr;
 
-pub struct Volume {
+pub const RADIANCE_MIPS: usize = 6;
+
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum VolumeStorage {
+	Dense,
+	Octree,
+	Clipmap,
+}
+
+impl VolumeStorage {
+	pub fn name(self) -> &'static str {
+		match self {
+			VolumeStorage::Dense => "dense",
+			VolumeStorage::Octree => "octree",
+			VolumeStorage::Clipmap => "clipmap",
+		}
+	}
+}
+
+impl FromStr for VolumeStorage {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<VolumeStorage, String> {
+		match s {
+			"dense" => Ok(VolumeStorage::Dense),
+			"octree" => Ok(VolumeStorage::Octree),
+			"clipmap" => Ok(VolumeStorage::Clipmap),
+			_ => Err(format!(
+				"Unknown volume storage '{}', expected dense, octree or clipmap",
+				s
+			)),
+		}
+	}
+}
+
+// The 3D textures of a dense volume
+struct DenseTextures {
 	albedo_id: u32,
 	normal_id: u32,
 	emission_id: u32,
 	radiance_id: u32,
+	// Only allocated once a dynamic primitive is voxelized on its own
+	overlay: Option<Overlay>,
+}
+
+impl DenseTextures {
+	fn new(resolution: usize) -> DenseTextures {
+		DenseTextures {
+			albedo_id: allocate_texture_3d(resolution, 1),
+			normal_id: allocate_texture_3d(resolution, 1),
+			emission_id: allocate_texture_3d(resolution, 1),
+			radiance_id: allocate_texture_3d(resolution, RADIANCE_MIPS),
+			overlay: None,
+		}
+	}
+}
+
+impl Drop for DenseTextures {
+	fn drop(&mut self) {
+		unsafe {
+			let textures = [
+				self.albedo_id,
+				self.normal_id,
+				self.emission_id,
+				self.radiance_id,
+			];
+			gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+		}
+	}
+}
+
+// The dynamic primitives voxelized on their own, over the static voxels of the dense textures
+struct Overlay {
+	albedo_id: u32,
+	normal_id: u32,
+	emission_id: u32,
+}
+
+impl Overlay {
+	fn new(resolution: usize) -> Overlay {
+		Overlay {
+			albedo_id: allocate_texture_3d(resolution, 1),
+			normal_id: allocate_texture_3d(resolution, 1),
+			emission_id: allocate_texture_3d(resolution, 1),
+		}
+	}
+}
+
+impl Drop for Overlay {
+	fn drop(&mut self) {
+		unsafe {
+			let textures = [self.albedo_id, self.normal_id, self.emission_id];
+			gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+		}
+	}
+}
+
+pub struct Volume {
+	storage: VolumeStorage,
+	// Only one of them is allocated, depending on the storage
+	dense: Option<DenseTextures>,
+	octree: Option<SparseOctree>,
+	clipmap: Option<Clipmap>,
 	resolution: usize,
-	primitive: GpuPrimitive,
+	cascade_count: usize,
 	translation: glm::Vec3,
 	scaling: glm::Vec3,
 	view_translation: glm::Vec3,
@@ -21,46 +122,153 @@ pub struct Volume {
 }
 
 impl Volume {
-	pub fn new(resolution: usize, program: &GLProgram) -> Volume {
-		let primitive = GpuPrimitive::from_volume(
-			[resolution as u32, resolution as u32, resolution as u32].into(),
-			&program,
-		);
-
-		Volume {
-			albedo_id: allocate_texture_3d(resolution, 1),
-			normal_id: allocate_texture_3d(resolution, 1),
-			emission_id: allocate_texture_3d(resolution, 1),
-			radiance_id: allocate_texture_3d(resolution, 6),
+	pub fn new(resolution: usize, storage: VolumeStorage, mipmap_program: GLProgram) -> Volume {
+		let mut volume = Volume {
+			storage,
+			dense: None,
+			octree: None,
+			clipmap: None,
 			resolution,
-			primitive,
+			cascade_count: MAX_CASCADES,
 			translation: glm::Vec3::new(0.0, 5.0, 0.0),
 			scaling: glm::Vec3::new(10.0, 10.0, 10.0),
 			view_translation: glm::Vec3::new(10.15, 5.0, 0.0),
 			view_scaling: glm::Vec3::new(10.0, 10.0, 10.0),
-			mipmap_program: load_mipmap_program(),
+			mipmap_program,
+		};
+		volume.allocate();
+
+		volume
+	}
+
+	fn allocate(&mut self) {
+		// The previous storage is dropped first so both are never in GPU memory at once
+		self.dense = None;
+		self.octree = None;
+		self.clipmap = None;
+
+		match self.storage {
+			VolumeStorage::Dense => self.dense = Some(DenseTextures::new(self.resolution)),
+			VolumeStorage::Octree => self.octree = Some(SparseOctree::new(self.resolution)),
+			VolumeStorage::Clipmap => {
+				self.clipmap = Some(Clipmap::new(self.resolution, self.cascade_count))
+			}
 		}
 	}
 
-	pub fn draw(&self) {
-		self.primitive.bind();
-		gl_draw_arrays(DrawMode::Points, 0, self.count_cells() as usize);
+	fn dense(&self) -> &DenseTextures {
+		self
+			.dense
+			.as_ref()
+			.expect("The volume isn't dense, it has no dense textures")
+	}
+
+	pub fn storage(&self) -> VolumeStorage {
+		self.storage
+	}
+
+	pub fn octree(&self) -> Option<&SparseOctree> {
+		self.octree.as_ref()
+	}
+
+	pub fn octree_mut(&mut self) -> Option<&mut SparseOctree> {
+		self.octree.as_mut()
+	}
+
+	pub fn clipmap(&self) -> Option<&Clipmap> {
+		self.clipmap.as_ref()
+	}
+
+	pub fn clipmap_mut(&mut self) -> Option<&mut Clipmap> {
+		self.clipmap.as_mut()
+	}
+
+	// The dense textures, the octree only voxelizes into the fragment list
+	pub fn voxel_target(&self) -> VoxelTarget {
+		let (albedo, normal, emission) = self.dense.as_ref().map_or((0, 0, 0), |dense| {
+			(dense.albedo_id, dense.normal_id, dense.emission_id)
+		});
+
+		VoxelTarget {
+			albedo,
+			normal,
+			emission,
+			resolution: self.resolution(),
+			pv: voxelization_pv(self),
+			region: VoxelRegion::whole(self.resolution),
+		}
+	}
+
+	// The overlay of the dense volume, where the dynamic primitives are voxelized every frame. It's
+	// allocated the first time it's needed
+	pub fn overlay_target(&mut self) -> VoxelTarget {
+		let resolution = self.resolution;
+		let dense = self
+			.dense
+			.as_mut()
+			.expect("The volume isn't dense, it has no dense textures");
+		let overlay = dense
+			.overlay
+			.get_or_insert_with(|| Overlay::new(resolution));
+		let (albedo, normal, emission) = (overlay.albedo_id, overlay.normal_id, overlay.emission_id);
+
+		VoxelTarget {
+			albedo,
+			normal,
+			emission,
+			resolution: self.resolution(),
+			pv: voxelization_pv(self),
+			region: VoxelRegion::whole(self.resolution),
+		}
+	}
+
+	// Empties the attributes and every mip of the radiance
+	pub fn clear(&self) {
+		let dense = self.dense();
+		clear_texture_3d(dense.albedo_id, 1);
+		clear_texture_3d(dense.normal_id, 1);
+		clear_texture_3d(dense.emission_id, 1);
+		clear_texture_3d(dense.radiance_id, RADIANCE_MIPS);
+	}
+
+	// Empties the overlay, when there is one, and the radiance, leaving the static voxels as they are
+	pub fn clear_overlay(&self) {
+		let dense = self.dense();
+		if let Some(overlay) = &dense.overlay {
+			clear_texture_3d(overlay.albedo_id, 1);
+			clear_texture_3d(overlay.normal_id, 1);
+			clear_texture_3d(overlay.emission_id, 1);
+		}
+		clear_texture_3d(dense.radiance_id, RADIANCE_MIPS);
+	}
+
+	pub fn bind_textures_overlay(&self, albedo: u32, normal: u32, emission: u32) {
+		let overlay = self
+			.dense()
+			.overlay
+			.as_ref()
+			.expect("The dense volume has no overlay yet");
+		unsafe {
+			gl::BindTextureUnit(albedo, overlay.albedo_id);
+			gl::BindTextureUnit(normal, overlay.normal_id);
+			gl::BindTextureUnit(emission, overlay.emission_id);
+		}
 	}
 
 	pub fn albedo_id(&self) -> u32 {
-		self.albedo_id
+		self.dense().albedo_id
 	}
 
 	pub fn normal_id(&self) -> u32 {
-		self.normal_id
+		self.dense().normal_id
 	}
 
 	pub fn emission_id(&self) -> u32 {
-		self.emission_id
+		self.dense().emission_id
 	}
 
 	pub fn radiance_id(&self) -> u32 {
-		self.radiance_id
+		self.dense().radiance_id
 	}
 
 	pub fn bind_image_albedo(&self, index: u32) {
@@ -143,6 +351,10 @@ impl Volume {
 		}
 	}
 
+	pub fn set_mipmap_program(&mut self, program: GLProgram) {
+		self.mipmap_program = program;
+	}
+
 	pub fn generate_mipmap(&self) {
 		unsafe {
 			// gl::GenerateTextureMipmap(self.albedo_id());
@@ -172,10 +384,53 @@ impl Volume {
 		// }
 	}
 
+	pub fn read_albedo(&self) -> Vec<[u8; 4]> {
+		self.read_texture(self.albedo_id())
+	}
+
+	pub fn read_normal(&self) -> Vec<[u8; 4]> {
+		self.read_texture(self.normal_id())
+	}
+
+	// Copies the first mip of a texture back to the CPU, x varies fastest
+	fn read_texture(&self, texture_id: u32) -> Vec<[u8; 4]> {
+		let mut pixels = vec![[0u8; 4]; self.count_cells()];
+		unsafe {
+			gl::MemoryBarrier(gl::TEXTURE_UPDATE_BARRIER_BIT);
+			gl::GetTextureImage(
+				texture_id,
+				0,
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+				(pixels.len() * mem::size_of::<[u8; 4]>()) as i32,
+				pixels.as_mut_ptr() as *mut _,
+			);
+		}
+
+		pixels
+	}
+
 	pub fn count_cells(&self) -> usize {
 		self.resolution * self.resolution * self.resolution
 	}
 
+	// The three attribute textures plus the radiance and its mips, without the overlay
+	pub fn dense_memory_bytes(resolution: usize) -> usize {
+		let texels = resolution * resolution * resolution;
+		let radiance_texels = (0..RADIANCE_MIPS)
+			.map(|mip| texels >> (3 * mip))
+			.sum::<usize>();
+		(3 * texels + radiance_texels) * 4
+	}
+
+	pub fn memory_bytes(&self) -> usize {
+		match (&self.octree, &self.clipmap) {
+			(Some(octree), _) => octree.memory_bytes(),
+			(_, Some(clipmap)) => clipmap.memory_bytes(),
+			_ => Volume::dense_memory_bytes(self.resolution) + 3 * self.count_cells() * 4,
+		}
+	}
+
 	pub fn resolution(&self) -> [i32; 3] {
 		[
 			self.resolution as i32,
@@ -184,8 +439,31 @@ impl Volume {
 		]
 	}
 
-	pub fn resolution_mut(&mut self) -> &mut usize {
-		&mut self.resolution
+	// Reallocates the volume, the contents are lost until the next voxelization
+	pub fn set_storage(&mut self, storage: VolumeStorage, resolution: usize) {
+		if storage == self.storage && resolution == self.resolution {
+			return;
+		}
+
+		self.storage = storage;
+		self.resolution = resolution;
+		self.allocate();
+	}
+
+	pub fn cascade_count(&self) -> usize {
+		self.cascade_count
+	}
+
+	// Only reallocates a clipmap, the other storages keep the count for later
+	pub fn set_cascade_count(&mut self, count: usize) {
+		if count == self.cascade_count {
+			return;
+		}
+
+		self.cascade_count = count;
+		if self.storage == VolumeStorage::Clipmap {
+			self.allocate();
+		}
 	}
 
 	pub const fn translation(&self) -> &glm::Vec3 {
@@ -221,6 +499,20 @@ impl Volume {
 	}
 }
 
+fn clear_texture_3d(texture: u32, mips: usize) {
+	for level in 0..mips {
+		unsafe {
+			gl::ClearTexImage(
+				texture,
+				level as i32,
+				gl::RGBA,
+				gl::UNSIGNED_BYTE,
+				ptr::null(),
+			);
+		}
+	}
+}
+
 pub fn allocate_texture_3d(resolution: usize, mipmap: usize) -> u32 {
 	use gl::*;
 
@@ -0,0 +1,167 @@
+use crate::gl_timer::{GlTimer, Sample};
+use imgui::{im_str, Ui};
+use std::collections::HashMap;
+
+const HISTORY_FRAMES: usize = 240;
+const FRAME_LABEL: &str = "frame";
+const BREAKDOWN_HEIGHT: f32 = 80.0;
+
+const PASS_COLORS: [[f32; 4]; 6] = [
+	[0.90, 0.35, 0.30, 1.0],
+	[0.30, 0.70, 0.40, 1.0],
+	[0.30, 0.50, 0.90, 1.0],
+	[0.90, 0.75, 0.25, 1.0],
+	[0.70, 0.40, 0.85, 1.0],
+	[0.30, 0.80, 0.85, 1.0],
+];
+
+struct LabelHistory {
+	name: String,
+	depth: usize,
+	samples: Vec<Sample>,
+}
+
+pub struct TimingGraphs {
+	paused: bool,
+	histograms: bool,
+	histories: Vec<LabelHistory>,
+}
+
+impl TimingGraphs {
+	pub fn new() -> TimingGraphs {
+		TimingGraphs {
+			paused: false,
+			histograms: false,
+			histories: Vec::new(),
+		}
+	}
+
+	// Takes a snapshot of the timer unless the graphs are frozen
+	pub fn update(&mut self, timer: &GlTimer) {
+		if self.paused {
+			return;
+		}
+
+		self.histories = timer
+			.summaries()
+			.iter()
+			.map(|summary| LabelHistory {
+				name: summary.name.to_owned(),
+				depth: summary.depth,
+				samples: timer.history(summary.name, HISTORY_FRAMES),
+			})
+			.collect();
+	}
+
+	pub fn draw(&mut self, ui: &Ui) {
+		let pause_label = if self.paused {
+			im_str!("Resume")
+		} else {
+			im_str!("Pause")
+		};
+		if ui.button(pause_label, [80.0, 20.0]) {
+			self.paused = !self.paused;
+		}
+		ui.same_line(0.0);
+		ui.checkbox(im_str!("Histograms"), &mut self.histograms);
+
+		self.draw_breakdown(ui);
+		ui.separator();
+
+		for history in &self.histories {
+			let values: Vec<f32> = history.samples.iter().map(|sample| sample.gpu_ms).collect();
+			let latest = values.last().cloned().unwrap_or(0.0);
+
+			let label = im_str!("{:indent$}{}", "", history.name, indent = history.depth * 2);
+			let overlay = im_str!("{:.3} ms", latest);
+
+			if self.histograms {
+				ui.plot_histogram(&label, &values)
+					.overlay_text(&overlay)
+					.scale_min(0.0)
+					.graph_size([0.0, 40.0])
+					.build();
+			} else {
+				ui.plot_lines(&label, &values)
+					.overlay_text(&overlay)
+					.scale_min(0.0)
+					.graph_size([0.0, 40.0])
+					.build();
+			}
+		}
+	}
+
+	// Direct children of the frame scope stacked on top of each other, one bar per frame
+	fn draw_breakdown(&self, ui: &Ui) {
+		let frames = match self
+			.histories
+			.iter()
+			.find(|history| history.name == FRAME_LABEL)
+		{
+			Some(frames) => frames,
+			None => return,
+		};
+
+		let passes: Vec<(&LabelHistory, HashMap<usize, f32>)> = self
+			.histories
+			.iter()
+			.filter(|history| history.depth == 1)
+			.map(|history| {
+				let times = history
+					.samples
+					.iter()
+					.map(|sample| (sample.frame, sample.gpu_ms))
+					.collect();
+				(history, times)
+			})
+			.collect();
+
+		let max_ms = frames
+			.samples
+			.iter()
+			.map(|sample| sample.gpu_ms)
+			.fold(0.001, f32::max);
+
+		let size = [ui.content_region_avail()[0], BREAKDOWN_HEIGHT];
+		let origin = ui.cursor_screen_pos();
+		let bar_width = size[0] / HISTORY_FRAMES as f32;
+
+		{
+			let draw_list = ui.get_window_draw_list();
+			draw_list
+				.add_rect(
+					origin,
+					[origin[0] + size[0], origin[1] + size[1]],
+					[0.1, 0.1, 0.1, 1.0],
+				)
+				.filled(true)
+				.build();
+
+			for (i, frame) in frames.samples.iter().enumerate() {
+				let x = origin[0] + i as f32 * bar_width;
+				let mut y = origin[1] + size[1];
+
+				for (j, (_, times)) in passes.iter().enumerate() {
+					if let Some(ms) = times.get(&frame.frame) {
+						let height = ms / max_ms * size[1];
+						draw_list
+							.add_rect([x, y - height], [x + bar_width, y], pass_color(j))
+							.filled(true)
+							.build();
+						y -= height;
+					}
+				}
+			}
+		}
+
+		ui.dummy(size);
+		ui.text(format!("Scale: {:.3} ms", max_ms));
+		for (j, (history, _)) in passes.iter().enumerate() {
+			ui.text_colored(pass_color(j), &history.name);
+		}
+	}
+}
+
+fn pass_color(index: usize) -> [f32; 4] {
+	PASS_COLORS[index % PASS_COLORS.len()]
+}
@@ -0,0 +1,106 @@
+use crate::renderer_utils::VoxelTarget;
+use crate::shader_layout::*;
+use gl;
+use gl_helpers::*;
+use std::mem::size_of;
+use std::ptr;
+
+// Matches `VoxelSums` in voxel_sums.glsl: nine 16 bit attribute sums, two to a word, and a count
+// per voxel. That is 384 MB at 256³
+const VOXEL_SIZE: usize = 6 * size_of::<u32>();
+
+// The fixed point attribute sums the voxelizers add to when they accumulate instead of averaging in
+// place. Allocated the first time a volume is voxelized this way
+pub struct VoxelSums {
+	handle: u32,
+	capacity: usize,
+}
+
+impl VoxelSums {
+	pub fn new() -> VoxelSums {
+		VoxelSums {
+			handle: 0,
+			capacity: 0,
+		}
+	}
+
+	// Room for `voxels` voxels, the contents are dropped when it grows
+	pub fn reserve(&mut self, voxels: usize) {
+		if voxels <= self.capacity {
+			return;
+		}
+
+		unsafe {
+			if self.handle != 0 {
+				gl::DeleteBuffers(1, &self.handle);
+			}
+			gl::CreateBuffers(1, &mut self.handle);
+			gl::NamedBufferData(
+				self.handle,
+				(voxels * VOXEL_SIZE) as isize,
+				ptr::null(),
+				gl::DYNAMIC_COPY,
+			);
+		}
+		self.capacity = voxels;
+	}
+
+	// Frees the sums until the next volume voxelized this way
+	pub fn release(&mut self) {
+		if self.handle != 0 {
+			unsafe {
+				gl::DeleteBuffers(1, &self.handle);
+			}
+		}
+		self.handle = 0;
+		self.capacity = 0;
+	}
+
+	pub fn clear(&self) {
+		unsafe {
+			gl::ClearNamedBufferData(
+				self.handle,
+				gl::R32UI,
+				gl::RED_INTEGER,
+				gl::UNSIGNED_INT,
+				ptr::null(),
+			);
+		}
+	}
+
+	pub fn bind_unit(&self, unit: u32) {
+		unsafe {
+			gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, unit, self.handle);
+		}
+	}
+
+	// Writes the average of every voxel that received samples into the textures of the target
+	pub fn resolve(&self, program: &GLProgram, target: &VoxelTarget) {
+		let resolution = &target.resolution;
+
+		program.bind();
+		RESOLUTION.set(resolution);
+		target.bind_images();
+		self.bind_unit(VOXEL_SUMS_BINDING);
+
+		unsafe {
+			gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+			gl::DispatchCompute(
+				(resolution[0] as u32 + 7) / 8,
+				(resolution[1] as u32 + 7) / 8,
+				(resolution[2] as u32 + 7) / 8,
+			);
+			gl::MemoryBarrier(gl::SHADER_IMAGE_ACCESS_BARRIER_BIT | gl::TEXTURE_FETCH_BARRIER_BIT);
+		}
+	}
+
+	pub fn memory_bytes(&self) -> usize {
+		self.capacity * VOXEL_SIZE
+	}
+}
+
+impl Drop for VoxelSums {
+	fn drop(&mut self) {
+		self.release();
+	}
+}
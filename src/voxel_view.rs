@@ -0,0 +1,330 @@
+use crate::shader_layout::*;
+use gl;
+use gl::types::*;
+use gl_helpers::*;
+use std::mem::size_of;
+use std::ptr;
+
+// Matches `VisibleVoxels` in voxel_compact.comp: a DrawArraysIndirectCommand and the count of
+// voxels found, then one packed position per voxel
+const HEADER: [u32; 5] = [CUBE_VERTICES, 0, 0, 0, 0];
+const HEADER_SIZE: usize = 5 * size_of::<u32>();
+// A cube is drawn as one triangle strip
+const CUBE_VERTICES: u32 = 14;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SliceAxis {
+	X,
+	Y,
+	Z,
+}
+
+impl SliceAxis {
+	pub const ALL: [SliceAxis; 3] = [SliceAxis::X, SliceAxis::Y, SliceAxis::Z];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			SliceAxis::X => "x",
+			SliceAxis::Y => "y",
+			SliceAxis::Z => "z",
+		}
+	}
+
+	// The voxel shown by a pixel of the slice at `index`, like slice_voxel in voxel_slice.comp
+	pub fn voxel(self, index: usize, pixel: [usize; 2]) -> [usize; 3] {
+		match self {
+			SliceAxis::X => [index, pixel[1], pixel[0]],
+			SliceAxis::Y => [pixel[0], index, pixel[1]],
+			SliceAxis::Z => [pixel[0], pixel[1], index],
+		}
+	}
+}
+
+// The texels of a volume texture the views show: one mip, without the voxels whose alpha is at or
+// under the threshold
+#[derive(Copy, Clone, PartialEq)]
+pub struct ViewedTexture {
+	pub texture: u32,
+	pub resolution: usize,
+	pub mip: usize,
+	pub threshold: f32,
+}
+
+impl ViewedTexture {
+	// Voxels per axis of the mip
+	pub fn size(&self) -> usize {
+		(self.resolution >> self.mip).max(1)
+	}
+}
+
+// The occupied voxels of one mip of a volume texture, compacted on the GPU into a list that is
+// drawn as one cube instance per voxel without reading anything back
+pub struct VoxelView {
+	buffer: u32,
+	vertex_array: u32,
+	capacity: usize,
+	// What the list holds, it is only compacted again when this or the volume changes
+	compacted: Option<ViewedTexture>,
+	// Signaled once the GPU finished the last compaction, its count is only read back then
+	pending_count: Option<GLsync>,
+	// 2D texture holding the last slice, recreated when the slice size changes
+	slice: u32,
+	slice_size: usize,
+}
+
+impl VoxelView {
+	pub fn new() -> VoxelView {
+		let mut vertex_array = 0;
+		unsafe {
+			gl::CreateVertexArrays(1, &mut vertex_array);
+			gl::EnableVertexArrayAttrib(vertex_array, VOXEL_ATTRIBUTE);
+			gl::VertexArrayAttribIFormat(vertex_array, VOXEL_ATTRIBUTE, 1, gl::UNSIGNED_INT, 0);
+			gl::VertexArrayAttribBinding(vertex_array, VOXEL_ATTRIBUTE, 0);
+			gl::VertexArrayBindingDivisor(vertex_array, 0, 1);
+		}
+
+		VoxelView {
+			buffer: 0,
+			vertex_array,
+			capacity: 0,
+			compacted: None,
+			pending_count: None,
+			slice: 0,
+			slice_size: 0,
+		}
+	}
+
+	// Room for `voxels` voxels, rounded up so a growing volume doesn't reallocate every time
+	fn reserve(&mut self, voxels: usize) {
+		if voxels <= self.capacity {
+			return;
+		}
+		let voxels = voxels.next_power_of_two();
+
+		unsafe {
+			if self.buffer != 0 {
+				gl::DeleteBuffers(1, &self.buffer);
+			}
+			gl::CreateBuffers(1, &mut self.buffer);
+			gl::NamedBufferData(
+				self.buffer,
+				(HEADER_SIZE + voxels * size_of::<u32>()) as isize,
+				ptr::null(),
+				gl::DYNAMIC_COPY,
+			);
+			gl::VertexArrayVertexBuffer(
+				self.vertex_array,
+				0,
+				self.buffer,
+				HEADER_SIZE as isize,
+				size_of::<u32>() as i32,
+			);
+		}
+		self.capacity = voxels;
+	}
+
+	// Lists the voxels of the viewed texture, unless the list already holds them and the volume
+	// didn't change. The list starts with room for a slice of voxels. The voxels that don't fit are
+	// left out of the draw, and the list grows and is compacted again once the count of the
+	// compaction is read back, a frame or more later when the GPU is done with it
+	pub fn compact(&mut self, program: &GLProgram, viewed: &ViewedTexture, volume_changed: bool) {
+		if self.overflowed() {
+			self.compacted = None;
+		}
+		if !volume_changed && self.compacted == Some(*viewed) {
+			return;
+		}
+
+		let size = viewed.size();
+		self.reserve(size * size);
+		self.dispatch_compact(program, viewed);
+		self.compacted = Some(*viewed);
+
+		unsafe {
+			if let Some(fence) = self.pending_count.take() {
+				gl::DeleteSync(fence);
+			}
+			self.pending_count = Some(gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0));
+		}
+	}
+
+	// Grows the list when the voxels of the last compaction didn't fit. Only reads their count
+	// once the compaction is finished, so this never stalls
+	fn overflowed(&mut self) -> bool {
+		let fence = match self.pending_count {
+			Some(fence) => fence,
+			None => return false,
+		};
+
+		let status = unsafe { gl::ClientWaitSync(fence, 0, 0) };
+		if status != gl::ALREADY_SIGNALED && status != gl::CONDITION_SATISFIED {
+			return false;
+		}
+		unsafe {
+			gl::DeleteSync(fence);
+		}
+		self.pending_count = None;
+
+		let count = self.found_count();
+		if count <= self.capacity {
+			return false;
+		}
+		self.reserve(count);
+		true
+	}
+
+	fn dispatch_compact(&self, program: &GLProgram, viewed: &ViewedTexture) {
+		let size = viewed.size();
+		unsafe {
+			gl::NamedBufferSubData(
+				self.buffer,
+				0,
+				HEADER_SIZE as isize,
+				HEADER.as_ptr() as *const GLvoid,
+			);
+		}
+
+		program.bind();
+		RESOLUTION.set(&[size as i32; 3]);
+		VIEW_MIP.set(viewed.mip as i32);
+		ALPHA_THRESHOLD.set(viewed.threshold);
+		unsafe {
+			gl::BindTextureUnit(ALBEDO_UNIT, viewed.texture);
+			gl::BindBufferBase(
+				gl::SHADER_STORAGE_BUFFER,
+				VISIBLE_VOXELS_BINDING,
+				self.buffer,
+			);
+
+			let groups = (size as u32 + 7) / 8;
+			gl::DispatchCompute(groups, groups, groups);
+			gl::MemoryBarrier(
+				gl::COMMAND_BARRIER_BIT
+					| gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT
+					| gl::BUFFER_UPDATE_BARRIER_BIT,
+			);
+		}
+	}
+
+	// Every voxel the last compaction found, including those that didn't fit
+	fn found_count(&self) -> usize {
+		let mut count = 0u32;
+		unsafe {
+			gl::GetNamedBufferSubData(
+				self.buffer,
+				(HEADER_SIZE - size_of::<u32>()) as isize,
+				size_of::<u32>() as isize,
+				&mut count as *mut u32 as *mut GLvoid,
+			);
+		}
+
+		count as usize
+	}
+
+	// Copies the plane `index` along `axis` of the viewed texture into a 2D texture and returns it
+	// with its size
+	pub fn slice(
+		&mut self,
+		program: &GLProgram,
+		viewed: &ViewedTexture,
+		axis: SliceAxis,
+		index: usize,
+	) -> (u32, usize) {
+		let size = viewed.size();
+		if size != self.slice_size {
+			self.allocate_slice(size);
+		}
+
+		program.bind();
+		VIEW_MIP.set(viewed.mip as i32);
+		ALPHA_THRESHOLD.set(viewed.threshold);
+		SLICE_AXIS.set(axis as i32);
+		SLICE_INDEX.set(index.min(size - 1) as i32);
+		unsafe {
+			gl::BindTextureUnit(ALBEDO_UNIT, viewed.texture);
+			gl::BindImageTexture(
+				SLICE_UNIT,
+				self.slice,
+				0,
+				gl::FALSE,
+				0,
+				gl::WRITE_ONLY,
+				gl::RGBA8,
+			);
+
+			let groups = (size as u32 + 7) / 8;
+			gl::DispatchCompute(groups, groups, 1);
+			gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+		}
+
+		(self.slice, size)
+	}
+
+	fn allocate_slice(&mut self, size: usize) {
+		use gl::*;
+
+		unsafe {
+			if self.slice != 0 {
+				DeleteTextures(1, &self.slice);
+			}
+			CreateTextures(TEXTURE_2D, 1, &mut self.slice);
+			// Every voxel stays a crisp square however large the slice is drawn
+			TextureParameteri(self.slice, TEXTURE_MIN_FILTER, NEAREST as i32);
+			TextureParameteri(self.slice, TEXTURE_MAG_FILTER, NEAREST as i32);
+			TextureStorage2D(self.slice, 1, RGBA8, size as i32, size as i32);
+		}
+		self.slice_size = size;
+	}
+
+	// Draws the cubes listed by the last `compact`, the view program must be bound
+	pub fn draw(&self) {
+		unsafe {
+			gl::BindVertexArray(self.vertex_array);
+			gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.buffer);
+			gl::DrawArraysIndirect(gl::TRIANGLE_STRIP, ptr::null());
+			gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+			gl::BindVertexArray(0);
+		}
+	}
+}
+
+impl Drop for VoxelView {
+	fn drop(&mut self) {
+		unsafe {
+			if let Some(fence) = self.pending_count {
+				gl::DeleteSync(fence);
+			}
+			if self.buffer != 0 {
+				gl::DeleteBuffers(1, &self.buffer);
+			}
+			if self.slice != 0 {
+				gl::DeleteTextures(1, &self.slice);
+			}
+			gl::DeleteVertexArrays(1, &self.vertex_array);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn slices_hold_the_index_along_their_axis() {
+		assert_eq!(SliceAxis::X.voxel(5, [1, 2]), [5, 2, 1]);
+		assert_eq!(SliceAxis::Y.voxel(5, [1, 2]), [1, 5, 2]);
+		assert_eq!(SliceAxis::Z.voxel(5, [1, 2]), [1, 2, 5]);
+	}
+
+	#[test]
+	fn slice_pixels_cover_distinct_voxels() {
+		for &axis in SliceAxis::ALL.iter() {
+			let mut voxels: Vec<[usize; 3]> = (0..3)
+				.flat_map(|u| (0..3).map(move |v| axis.voxel(1, [u, v])))
+				.collect();
+			voxels.sort();
+			voxels.dedup();
+			assert_eq!(voxels.len(), 9, "{}", axis.name());
+		}
+	}
+}